@@ -0,0 +1,604 @@
+//! The client for GitHub's API built on async `reqwest`, for callers already
+//! running a Tokio runtime. It shares the query building, response types and
+//! error handling of the blocking client, which covers a lot more of the API.
+
+use super::shared::*;
+
+use anyhow::{bail, format_err, Error};
+use futures_util::future::try_join_all;
+use log::{debug, warn};
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use reqwest::{Client, ClientBuilder, Method, RequestBuilder, Response, StatusCode};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+/// An async GitHub client. Clones share the limit on concurrent requests,
+/// the request statistics and what was learned from responses.
+#[derive(Clone)]
+pub struct AsyncGitHubApi {
+    http: Client,
+    token: Option<String>,
+    clock: Arc<dyn Clock>,
+    api_base: String,
+    graphql_url: Option<String>,
+    api_version: String,
+    max_retries: u32,
+    rate_limit_waits: bool,
+    max_response_size: Option<u64>,
+    permits: Arc<Semaphore>,
+    requests: Arc<Mutex<RequestStats>>,
+    responses: Arc<Mutex<ResponseState>>,
+    shut_down: Arc<AtomicBool>,
+}
+
+impl Default for AsyncGitHubApi {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AsyncGitHubApi {
+    /// Authenticate with the token in `GITHUB_TOKEN`, or anonymously
+    /// without one.
+    pub fn new() -> Self {
+        Self::from_auth(std::env::var(TOKEN_VAR).ok())
+    }
+
+    /// Authenticate with `token` instead of reading it from `GITHUB_TOKEN`.
+    pub fn from_token(token: impl Into<String>) -> Self {
+        Self::from_auth(Some(token.into()))
+    }
+
+    fn from_auth(token: Option<String>) -> Self {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT_LANGUAGE, HeaderValue::from_static("en"));
+        AsyncGitHubApi {
+            http: ClientBuilder::new()
+                .user_agent(crate::USER_AGENT)
+                .default_headers(headers)
+                .build()
+                .unwrap(),
+            token,
+            clock: Arc::new(SystemClock),
+            api_base: API_BASE.into(),
+            graphql_url: None,
+            api_version: DEFAULT_API_VERSION.into(),
+            max_retries: DEFAULT_MAX_RETRIES,
+            rate_limit_waits: false,
+            max_response_size: None,
+            permits: Arc::new(Semaphore::new(DEFAULT_MAX_CONCURRENCY)),
+            requests: Arc::new(Mutex::new(RequestStats::default())),
+            responses: Arc::new(Mutex::new(ResponseState::default())),
+            shut_down: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Talk to another instance than GitHub.com, like GitHub Enterprise at
+    /// `https://github.example.com/api/v3`.
+    pub fn with_api_base(mut self, url: &str) -> Self {
+        self.api_base = format!("{}/", url.trim_end_matches('/'));
+        self
+    }
+
+    /// Where to send GraphQL queries, if not under the REST API base.
+    pub fn with_graphql_url(mut self, url: &str) -> Self {
+        self.graphql_url = Some(url.into());
+        self
+    }
+
+    /// How many requests can be in flight at the same time, across all the
+    /// tasks using this client or its clones. The batch methods start every
+    /// request at once and let this limit how many are actually sent.
+    /// Defaults to 8, as for the blocking client.
+    pub fn with_max_concurrency(mut self, max: usize) -> Self {
+        self.permits = Arc::new(Semaphore::new(max.max(1)));
+        self
+    }
+
+    /// How many times a request is retried after a server error or after
+    /// failing to reach GitHub at all. Requests aren't retried by default.
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Wait for exhausted rate limits to reset and send the request again,
+    /// instead of failing right away as by default.
+    pub fn with_rate_limit_waits(mut self, wait: bool) -> Self {
+        self.rate_limit_waits = wait;
+        self
+    }
+
+    /// Fail with [`GitHubError::ResponseTooLarge`] instead of reading bodies
+    /// larger than `limit` bytes.
+    pub fn with_max_response_size(mut self, limit: u64) -> Self {
+        self.max_response_size = Some(limit);
+        self
+    }
+
+    /// Replace the time source used when waiting for rate limits and retries.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Stop waiting for rate limits and retries, in this client and all its
+    /// clones: pending and future waits fail with [`GitHubError::Cancelled`]
+    /// within a second.
+    pub fn shutdown(&self) {
+        self.shut_down.store(true, Ordering::SeqCst);
+    }
+
+    pub fn request_stats(&self) -> RequestStats {
+        *self.requests.lock().unwrap()
+    }
+
+    /// The rate limit of the bucket, as reported by the most recent response
+    /// counted in it.
+    pub fn rate_limit(&self, resource: RateLimitResource) -> Option<RateLimit> {
+        self.responses
+            .lock()
+            .unwrap()
+            .rate_limits
+            .get(&resource)
+            .copied()
+    }
+
+    fn record(&self, resp: &Response) {
+        self.responses.lock().unwrap().record(
+            resp.status(),
+            resp.url().as_str(),
+            resp.headers(),
+            &self.api_version,
+            None,
+            self.clock.now(),
+        );
+    }
+
+    fn graphql_url(&self) -> String {
+        match &self.graphql_url {
+            Some(url) => url.clone(),
+            None => format!("{}graphql", self.api_base),
+        }
+    }
+
+    fn prepare(
+        &self,
+        require_auth: bool,
+        method: Method,
+        url: &str,
+    ) -> Result<RequestBuilder, Error> {
+        let url = if url.starts_with("https://") || url.starts_with("http://") {
+            url.to_string()
+        } else {
+            format!("{}{}", self.api_base, url)
+        };
+        let mut req = self
+            .http
+            .request(method, url)
+            .header("x-github-api-version", &self.api_version);
+        match &self.token {
+            Some(token) => req = req.header(header::AUTHORIZATION, authorization(token)?),
+            None if require_auth => bail!("missing environment variable {}", TOKEN_VAR),
+            None => {}
+        }
+        Ok(req)
+    }
+
+    /// Send a request, retrying server errors and connection failures with an
+    /// exponential backoff and waiting out exhausted rate limits, as far as
+    /// the client is configured to. This client only sends requests that are
+    /// safe to repeat, so they all get the same budget of retries.
+    async fn send(&self, req: RequestBuilder) -> Result<Response, Error> {
+        let req = req.build()?;
+        let mut attempt = 0;
+        let mut rate_limit_retries = 0;
+        loop {
+            let attempt_req = req
+                .try_clone()
+                .ok_or_else(|| format_err!("request body can't be retried"))?;
+            let permit = self.permits.acquire().await?;
+            {
+                let stats = &mut self.requests.lock().unwrap();
+                stats.sent += 1;
+                stats.in_flight += 1;
+                stats.max_in_flight = stats.max_in_flight.max(stats.in_flight);
+            }
+            let result = self.http.execute(attempt_req).await;
+            self.requests.lock().unwrap().in_flight -= 1;
+            drop(permit);
+            let resp = match result {
+                Ok(resp) => resp,
+                Err(err)
+                    if should_retry(Err(&err), attempt, self.max_retries, self.max_retries) =>
+                {
+                    debug!("failed to reach GitHub ({}), retrying", err);
+                    self.backoff(attempt).await?;
+                    attempt += 1;
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            };
+            self.record(&resp);
+            if self.rate_limit_waits && rate_limit_retries < MAX_RATE_LIMIT_RETRIES {
+                let wait = bounded_rate_limit_wait(resp.status(), resp.headers(), self.clock.now());
+                if let Some(wait) = wait {
+                    warn!("hit the GitHub rate limit, waiting {}s", wait.as_secs());
+                    self.sleep(wait).await?;
+                    rate_limit_retries += 1;
+                    continue;
+                }
+            }
+            if should_retry(
+                Ok(resp.status()),
+                attempt,
+                self.max_retries,
+                self.max_retries,
+            ) {
+                debug!("{} returned {}, retrying", resp.url(), resp.status());
+                self.backoff(attempt).await?;
+                attempt += 1;
+                continue;
+            }
+            return Ok(resp);
+        }
+    }
+
+    async fn backoff(&self, attempt: u32) -> Result<(), Error> {
+        self.sleep(RETRY_BASE_DELAY * 2u32.pow(attempt)).await
+    }
+
+    /// Sleep in short slices, to stop early once the client is shut down.
+    /// Sleeping on the clock blocks, so it's done on Tokio's blocking threads
+    /// rather than stalling the runtime.
+    async fn sleep(&self, duration: Duration) -> Result<(), Error> {
+        let mut left = duration;
+        loop {
+            if self.shut_down.load(Ordering::SeqCst) {
+                return Err(GitHubError::Cancelled.into());
+            }
+            if left.is_zero() {
+                return Ok(());
+            }
+            let slice = left.min(SHUTDOWN_POLL_INTERVAL);
+            let clock = self.clock.clone();
+            tokio::task::spawn_blocking(move || clock.sleep(slice)).await?;
+            left -= slice;
+        }
+    }
+
+    /// Like [`Self::send`], but turn non-2xx responses into errors, unless
+    /// their status is in `acceptable`.
+    async fn send_checked(
+        &self,
+        req: RequestBuilder,
+        acceptable: &[StatusCode],
+    ) -> Result<Response, Error> {
+        let resp = self.send(req).await?;
+        let status = resp.status();
+        if status.is_success() || acceptable.contains(&status) {
+            Ok(resp)
+        } else {
+            Err(api_error(resp).await)
+        }
+    }
+
+    /// Read the whole body, unless it's larger than the maximum response size.
+    async fn read_body(&self, resp: Response) -> Result<Vec<u8>, Error> {
+        let request_id = request_id(resp.headers());
+        self.read_body_unchecked(resp).await.map_err(|err| {
+            err.context(format!(
+                "failed to read the response{}",
+                RequestIdSuffix(&request_id)
+            ))
+        })
+    }
+
+    async fn read_body_unchecked(&self, mut resp: Response) -> Result<Vec<u8>, Error> {
+        let limit = match self.max_response_size {
+            Some(limit) => limit,
+            None => return Ok(resp.bytes().await?.to_vec()),
+        };
+        let too_large = GitHubError::ResponseTooLarge {
+            url: resp.url().to_string(),
+            limit,
+        };
+        // The length of compressed bodies isn't known in advance, so this
+        // can't replace counting what's read.
+        if resp.content_length().is_some_and(|len| len > limit) {
+            return Err(too_large.into());
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = resp.chunk().await? {
+            body.extend_from_slice(&chunk);
+            if body.len() as u64 > limit {
+                return Err(too_large.into());
+            }
+        }
+        Ok(body)
+    }
+
+    async fn read_json<T: serde::de::DeserializeOwned>(&self, resp: Response) -> Result<T, Error> {
+        let request_id = request_id(resp.headers());
+        let body = self.read_body(resp).await?;
+        serde_json::from_slice(&body).map_err(|err| {
+            Error::from(err).context(format!(
+                "failed to parse the response{}",
+                RequestIdSuffix(&request_id)
+            ))
+        })
+    }
+
+    /// Send a GraphQL query, retrying it while it fails with retriable
+    /// errors, and parse its data.
+    async fn graphql<R, V>(&self, query: &str, variables: V) -> Result<R, Error>
+    where
+        R: serde::de::DeserializeOwned,
+        V: serde::Serialize,
+    {
+        #[derive(serde::Serialize)]
+        struct Request<'a, V> {
+            query: &'a str,
+            variables: V,
+        }
+        let mut attempt = 0;
+        loop {
+            let req = self
+                .prepare(true, Method::POST, &self.graphql_url())?
+                .json(&Request {
+                    query,
+                    variables: &variables,
+                });
+            let resp = self.send_checked(req, &[StatusCode::NOT_FOUND]).await?;
+            // Some locked down GitHub Enterprise instances disable GraphQL.
+            let request_id = request_id(resp.headers());
+            if resp.status() == StatusCode::NOT_FOUND {
+                return Err(GitHubError::GraphQlUnavailable { request_id }.into());
+            }
+            let mut res: GraphResult<serde_json::Value> = self.read_json(resp).await?;
+            res.set_request_id(request_id);
+            match res.errors.first() {
+                Some(error) if error.is_retriable() && attempt < self.max_retries => {
+                    debug!("{}, retrying", error);
+                    self.backoff(attempt).await?;
+                    attempt += 1;
+                }
+                Some(_) => return Err(res.into_error()),
+                None => match res.data.take() {
+                    Some(data) => return res.parse(data),
+                    None => return Err(res.into_error()),
+                },
+            }
+        }
+    }
+
+    /// Fetch every item of a REST list endpoint, with the given query
+    /// parameters, following the `Link` header through all the pages.
+    pub async fn list<T>(&self, path: &str, params: &[(&str, &str)]) -> Result<Vec<T>, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let per_page = REST_PAGE_SIZE.to_string();
+        let mut req = self
+            .prepare(true, Method::GET, path)?
+            .query(params)
+            .query(&[("per_page", &per_page)]);
+        let mut items = Vec::new();
+        loop {
+            let resp = self.send_checked(req, &[]).await?;
+            // The next page's URL already includes all the query parameters.
+            let next = next_link(resp.headers());
+            items.extend(self.read_json::<Vec<T>>(resp).await?);
+            match next {
+                Some(url) => req = self.prepare(true, Method::GET, &url)?,
+                None => return Ok(items),
+            }
+        }
+    }
+
+    /// Fetch a user, failing with [`GitHubError::IsOrganization`] if the login
+    /// is the one of an organization.
+    pub async fn user(&self, login: &str) -> Result<User, Error> {
+        let req = self.prepare(false, Method::GET, &format!("users/{}", login))?;
+        let resp = self.send_checked(req, &[]).await?;
+        let request_id = request_id(resp.headers());
+        let user: User = self.read_json(resp).await?;
+        if user.is_organization() {
+            return Err(GitHubError::IsOrganization {
+                login: user.login,
+                request_id,
+            }
+            .into());
+        }
+        Ok(user)
+    }
+
+    /// Fetch many users concurrently, in the same order as `logins`.
+    pub async fn users(&self, logins: &[&str]) -> Result<Vec<User>, Error> {
+        try_join_all(logins.iter().map(|login| self.user(login))).await
+    }
+
+    /// Resolve user ids to logins, querying chunks of them concurrently.
+    pub async fn usernames(&self, ids: &[u64]) -> Result<HashMap<u64, String>, Error> {
+        let chunks = ids.chunks(USERNAMES_CHUNK_SIZE).map(|chunk| {
+            self.graphql::<GraphNodes<Usernames>, _>(USERNAMES_QUERY, UsernamesParams::new(chunk))
+        });
+        Ok(try_join_all(chunks)
+            .await?
+            .into_iter()
+            .flat_map(|res| res.nodes.into_iter().flatten())
+            .map(|node| (node.database_id, node.login))
+            .collect())
+    }
+}
+
+/// Build the error for an unsuccessful response, out of its body.
+async fn api_error(mut resp: Response) -> Error {
+    let status = resp.status();
+    let url = resp.url().to_string();
+    let request_id = request_id(resp.headers());
+    // Error bodies are small, unless something else than GitHub answered.
+    let mut body = Vec::new();
+    while let Ok(Some(chunk)) = resp.chunk().await {
+        body.extend_from_slice(&chunk);
+        if body.len() as u64 >= MAX_ERROR_BODY_SIZE {
+            body.truncate(MAX_ERROR_BODY_SIZE as usize);
+            break;
+        }
+    }
+    let body = String::from_utf8_lossy(&body);
+    ApiError {
+        request_id,
+        ..ApiError::from_body(status, url, &body)
+    }
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github::mock::{ManualClock, MockServer, Reply};
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn limits_concurrency_of_batches() {
+        let server = MockServer::start(|req| {
+            // Keep the requests in flight long enough to pile up.
+            std::thread::sleep(Duration::from_millis(20));
+            let login = req.request_line.split(' ').nth(1).unwrap()["/users/".len()..].to_string();
+            Reply::json(
+                200,
+                format!(r#"{{"id": 1, "login": "{login}", "type": "User"}}"#),
+            )
+        });
+        let api = AsyncGitHubApi::from_token("secret")
+            .with_api_base(&server.addr)
+            .with_max_concurrency(2);
+        let logins = ["a", "b", "c", "d", "e", "f"];
+        let users = runtime().block_on(api.users(&logins)).unwrap();
+        assert_eq!(
+            users.iter().map(|u| u.login.as_str()).collect::<Vec<_>>(),
+            logins
+        );
+        let stats = api.request_stats();
+        assert_eq!(stats.sent, 6);
+        assert_eq!(stats.in_flight, 0);
+        assert_eq!(stats.max_in_flight, 2);
+        assert_eq!(server.finish().len(), 6);
+    }
+
+    #[test]
+    fn surfaces_api_errors() {
+        let server = MockServer::start(|_| {
+            Reply::json(
+                422,
+                r#"{"message": "Validation Failed", "errors": [{"code": "invalid"}]}"#,
+            )
+        });
+        let api = AsyncGitHubApi::from_token("secret").with_api_base(&server.addr);
+        let err = match runtime().block_on(api.user("alice")) {
+            Ok(_) => panic!("validation errors are errors"),
+            Err(err) => err,
+        };
+        assert_eq!(err.downcast_ref::<ApiError>().unwrap().status, 422);
+        server.finish();
+    }
+
+    fn alice() -> Reply {
+        Reply::json(200, r#"{"id": 1, "login": "alice", "type": "User"}"#)
+    }
+
+    #[test]
+    fn waits_out_rate_limits_on_its_clock() {
+        let mut replies = vec![
+            Reply::status(403)
+                .header("x-ratelimit-remaining", "0")
+                .header("x-ratelimit-reset", "1030"),
+            alice()
+                .header("x-ratelimit-remaining", "4999")
+                .header("x-ratelimit-reset", "4600"),
+        ]
+        .into_iter();
+        let server = MockServer::start(move |_| replies.next().unwrap_or(Reply::status(404)));
+        let clock = Arc::new(ManualClock::at(1000));
+        let api = AsyncGitHubApi::from_token("secret")
+            .with_api_base(&server.addr)
+            .with_clock(clock.clone())
+            .with_rate_limit_waits(true);
+
+        let user = runtime().block_on(api.user("alice")).unwrap();
+        assert_eq!(user.login, "alice");
+        assert_eq!(server.finish().len(), 2);
+        assert_eq!(clock.slept(), Duration::from_secs(30));
+        assert_eq!(
+            api.rate_limit(RateLimitResource::Core).unwrap().remaining,
+            4999
+        );
+    }
+
+    #[test]
+    fn retries_server_errors_within_budget() {
+        let mut replies = vec![Reply::status(502), Reply::status(502), alice()].into_iter();
+        let server = MockServer::start(move |_| replies.next().unwrap_or(Reply::status(502)));
+        let clock = Arc::new(ManualClock::at(0));
+        let api = AsyncGitHubApi::from_token("secret")
+            .with_api_base(&server.addr)
+            .with_clock(clock.clone());
+
+        // Requests aren't retried by default.
+        assert!(runtime().block_on(api.user("alice")).is_err());
+        let api = api.with_max_retries(1);
+        assert_eq!(
+            runtime().block_on(api.user("alice")).unwrap().login,
+            "alice"
+        );
+        assert_eq!(server.finish().len(), 3);
+        assert_eq!(clock.slept(), RETRY_BASE_DELAY);
+    }
+
+    #[test]
+    fn stops_waiting_on_shutdown() {
+        let server = MockServer::start(|_| Reply::status(503));
+        let clock = Arc::new(ManualClock::at(0));
+        let api = AsyncGitHubApi::from_token("secret")
+            .with_api_base(&server.addr)
+            .with_clock(clock.clone())
+            .with_max_retries(3);
+        api.clone().shutdown();
+
+        let err = match runtime().block_on(api.user("alice")) {
+            Ok(_) => panic!("the client was shut down"),
+            Err(err) => err,
+        };
+        assert!(matches!(err.downcast_ref(), Some(GitHubError::Cancelled)));
+        assert_eq!(server.finish().len(), 1);
+        assert_eq!(clock.slept(), Duration::ZERO);
+    }
+
+    #[test]
+    fn rejects_responses_over_the_size_limit() {
+        let server = MockServer::start(|_| alice());
+        let api = AsyncGitHubApi::from_token("secret")
+            .with_api_base(&server.addr)
+            .with_max_response_size(16);
+
+        let err = match runtime().block_on(api.user("alice")) {
+            Ok(_) => panic!("the response is too large"),
+            Err(err) => err,
+        };
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(GitHubError::ResponseTooLarge { limit: 16, .. })
+        ));
+        server.finish();
+    }
+}
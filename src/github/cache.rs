@@ -0,0 +1,102 @@
+//! What the client remembers between requests to avoid sending them again.
+//! The caches live in the client's shared state, so clones share them.
+
+use std::collections::{HashMap, HashSet};
+
+/// Which caches are enabled, and how large they can grow.
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    /// Remember the members of each organization checked by
+    /// [`GitHubApi::is_org_member`](super::GitHubApi::is_org_member).
+    pub org_members: bool,
+    /// How many usernames resolved from user ids to remember, `0` disabling
+    /// the cache.
+    pub usernames: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            org_members: true,
+            usernames: 0,
+        }
+    }
+}
+
+#[derive(Default)]
+pub(super) struct Caches {
+    config: CacheConfig,
+    /// Lowercased logins of the members of each organization, keyed by the
+    /// lowercased organization name.
+    org_members: HashMap<String, HashSet<String>>,
+    usernames: HashMap<u64, String>,
+}
+
+impl Caches {
+    pub(super) fn new(config: CacheConfig) -> Self {
+        Caches {
+            config,
+            ..Caches::default()
+        }
+    }
+
+    pub(super) fn config(&self) -> &CacheConfig {
+        &self.config
+    }
+
+    pub(super) fn org_members(&self, org: &str) -> Option<&HashSet<String>> {
+        self.org_members.get(org)
+    }
+
+    pub(super) fn insert_org_members(&mut self, org: String, members: HashSet<String>) {
+        if self.config.org_members {
+            self.org_members.insert(org, members);
+        }
+    }
+
+    pub(super) fn invalidate_org(&mut self, org: &str) {
+        self.org_members.remove(org);
+    }
+
+    pub(super) fn username(&self, id: u64) -> Option<&String> {
+        self.usernames.get(&id)
+    }
+
+    /// Remember usernames until the cache is full, after which new ones are
+    /// dropped: the ones already known are as likely to be asked for again.
+    pub(super) fn insert_usernames(&mut self, usernames: impl IntoIterator<Item = (u64, String)>) {
+        for (id, login) in usernames {
+            if self.usernames.len() >= self.config.usernames {
+                break;
+            }
+            self.usernames.insert(id, login);
+        }
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.org_members.clear();
+        self.usernames.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn respects_config() {
+        let mut caches = Caches::new(CacheConfig {
+            org_members: false,
+            usernames: 2,
+        });
+        caches.insert_org_members("rust-lang".into(), HashSet::new());
+        assert!(caches.org_members("rust-lang").is_none());
+
+        caches.insert_usernames((1..=3).map(|id| (id, format!("user{id}"))));
+        assert_eq!(caches.username(2).map(|s| s.as_str()), Some("user2"));
+        assert!(caches.username(3).is_none());
+
+        caches.clear();
+        assert!(caches.username(1).is_none());
+    }
+}
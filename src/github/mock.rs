@@ -0,0 +1,181 @@
+//! A local HTTP server answering the clients' requests in tests.
+
+use super::Clock;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A request received by a [`MockServer`].
+#[derive(Debug)]
+pub(super) struct Received {
+    /// Like `GET /user HTTP/1.1`.
+    pub(super) request_line: String,
+    /// Keyed by lowercased name.
+    pub(super) headers: HashMap<String, String>,
+    pub(super) body: String,
+}
+
+/// A response sent by a [`MockServer`].
+pub(super) struct Reply {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+}
+
+impl Reply {
+    pub(super) fn status(status: u16) -> Self {
+        Reply {
+            status,
+            headers: Vec::new(),
+            body: String::new(),
+        }
+    }
+
+    pub(super) fn json(status: u16, body: impl Into<String>) -> Self {
+        Reply {
+            body: body.into(),
+            ..Reply::status(status).header("content-type", "application/json")
+        }
+    }
+
+    pub(super) fn text(status: u16, body: impl Into<String>) -> Self {
+        Reply {
+            body: body.into(),
+            ..Reply::status(status).header("content-type", "text/plain; charset=utf-8")
+        }
+    }
+
+    pub(super) fn header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+}
+
+/// Answers requests on a local port, one connection at a time, until it's
+/// finished.
+pub(super) struct MockServer {
+    pub(super) addr: String,
+    stop: Arc<AtomicBool>,
+    handle: std::thread::JoinHandle<Vec<Received>>,
+}
+
+impl MockServer {
+    pub(super) fn start(mut respond: impl FnMut(&Received) -> Reply + Send + 'static) -> Self {
+        use std::io::{BufRead, BufReader};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let addr = format!("http://{}", listener.local_addr().unwrap());
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let handle = std::thread::spawn(move || {
+            let mut received = Vec::new();
+            loop {
+                let stream = match listener.accept() {
+                    Ok((stream, _)) => stream,
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                        if stopped.load(Ordering::SeqCst) {
+                            return received;
+                        }
+                        std::thread::sleep(Duration::from_millis(5));
+                        continue;
+                    }
+                    Err(e) => panic!("{}", e),
+                };
+                stream.set_nonblocking(false).unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut headers = HashMap::new();
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    if let Some((name, value)) = line.split_once(':') {
+                        headers.insert(name.to_lowercase(), value.trim().to_string());
+                    }
+                    line.clear();
+                }
+                let length = headers
+                    .get("content-length")
+                    .map_or(0, |l| l.parse().unwrap());
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let request = Received {
+                    request_line: request_line.trim().to_string(),
+                    headers,
+                    body: String::from_utf8(body).unwrap(),
+                };
+                let reply = respond(&request);
+                let mut response = format!(
+                    "HTTP/1.1 {} X\r\ncontent-length: {}\r\nconnection: close\r\n",
+                    reply.status,
+                    reply.body.len()
+                );
+                for (name, value) in &reply.headers {
+                    response.push_str(&format!("{name}: {value}\r\n"));
+                }
+                response.push_str("\r\n");
+                response.push_str(&reply.body);
+                // The client is gone if it timed out.
+                let _ = reader.get_mut().write_all(response.as_bytes());
+                received.push(request);
+            }
+        });
+        MockServer { addr, stop, handle }
+    }
+
+    /// Stop listening, returning every request received.
+    pub(super) fn finish(self) -> Vec<Received> {
+        self.stop.store(true, Ordering::SeqCst);
+        self.handle.join().unwrap()
+    }
+}
+
+/// Answers with an empty body and these statuses in order, then 404.
+pub(super) fn serve(statuses: &'static [u16]) -> MockServer {
+    let mut statuses = statuses.iter();
+    MockServer::start(move |_| Reply::status(*statuses.next().unwrap_or(&404)))
+}
+
+pub(super) fn authorizations(received: &[Received]) -> Vec<&str> {
+    received
+        .iter()
+        .map(|r| r.headers.get("authorization").map_or("", |a| a.as_str()))
+        .collect()
+}
+
+/// A clock that only moves forward when something sleeps on it.
+pub(super) struct ManualClock {
+    now: Mutex<SystemTime>,
+    pub(super) sleeps: Mutex<Vec<Duration>>,
+}
+
+impl ManualClock {
+    pub(super) fn at(secs: u64) -> Self {
+        ManualClock {
+            now: Mutex::new(UNIX_EPOCH + Duration::from_secs(secs)),
+            sleeps: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// How long was slept in total.
+    pub(super) fn slept(&self) -> Duration {
+        self.sleeps.lock().unwrap().iter().sum()
+    }
+
+    pub(super) fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.sleeps.lock().unwrap().push(duration);
+        self.advance(duration);
+    }
+}
@@ -0,0 +1,28 @@
+//! Clients for GitHub's REST and GraphQL APIs.
+//!
+//! The transport-agnostic part (query building, response types and error
+//! handling) lives in `shared`, used by the blocking client behind the
+//! `blocking` feature and the async one behind the `async` feature.
+
+#[cfg(feature = "async")]
+pub mod asynchronous;
+#[cfg(feature = "blocking")]
+mod blocking;
+// Some helpers here and in `mock` and `shared` are only used by the
+// blocking client.
+#[cfg_attr(not(feature = "blocking"), allow(dead_code))]
+mod cache;
+#[cfg(test)]
+#[cfg_attr(not(feature = "blocking"), allow(dead_code))]
+mod mock;
+#[cfg(feature = "blocking")]
+pub mod reconcile;
+#[cfg(feature = "blocking")]
+pub mod report;
+#[cfg_attr(not(feature = "blocking"), allow(dead_code))]
+mod shared;
+
+#[cfg(feature = "blocking")]
+pub use self::blocking::*;
+pub use self::cache::CacheConfig;
+pub use self::shared::*;
@@ -0,0 +1,636 @@
+//! Planning the changes needed to bring GitHub in line with the desired
+//! state, in an order that can be applied without spurious failures.
+
+use super::{GitHubApi, OrgRole, TeamRole, User};
+use anyhow::{bail, Error};
+use log::warn;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// The members of each team of an organization, keyed by team slug and login.
+pub type Teams = BTreeMap<String, BTreeMap<String, TeamRole>>;
+
+/// What an organization currently looks like on GitHub.
+#[derive(Debug, Default)]
+pub struct OrgState {
+    /// Members of the organization, including pending invitations.
+    pub members: BTreeSet<String>,
+    pub teams: Teams,
+    /// Whether each repository is archived, keyed by name.
+    pub archived: BTreeMap<String, bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    AddToOrg {
+        org: String,
+        login: String,
+    },
+    SetTeamMembership {
+        org: String,
+        team: String,
+        login: String,
+        role: TeamRole,
+    },
+    /// Turn a maintainer of the team back into a plain member.
+    DemoteToMember {
+        org: String,
+        team: String,
+        login: String,
+    },
+    RemoveFromTeam {
+        org: String,
+        team: String,
+        login: String,
+    },
+    SetRepoArchived {
+        owner: String,
+        repo: String,
+        archived: bool,
+    },
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operation::AddToOrg { org, login } => write!(f, "add {} to the {} org", login, org),
+            Operation::SetTeamMembership {
+                org,
+                team,
+                login,
+                role,
+            } => write!(f, "set {} as {:?} of {}/{}", login, role, org, team),
+            Operation::DemoteToMember { org, team, login } => {
+                write!(f, "demote {} to Member of {}/{}", login, org, team)
+            }
+            Operation::RemoveFromTeam { org, team, login } => {
+                write!(f, "remove {} from {}/{}", login, org, team)
+            }
+            Operation::SetRepoArchived {
+                owner,
+                repo,
+                archived,
+            } => {
+                let action = if *archived { "archive" } else { "unarchive" };
+                write!(f, "{} {}/{}", action, owner, repo)
+            }
+        }
+    }
+}
+
+/// Compute the operations turning `current` into `desired`, and archiving or
+/// unarchiving the repositories of `archived` whose state differs. Unknown
+/// repositories are left alone.
+///
+/// Users have to be part of the organization before they can be added to one
+/// of its teams, so organization additions come first, then team additions
+/// and role changes, and removals last: this way someone moving between teams
+/// never transiently loses all their access. Archival is ordered by
+/// [`order_archival`].
+///
+/// Maintainers listed as plain members are demoted, unless `protect_downgrades`
+/// is set: a missing role in the desired state is more often an omission than
+/// an intent to strip someone's rights, so those are then only warned about.
+pub fn plan(
+    org: &str,
+    current: &OrgState,
+    desired: &Teams,
+    archived: &BTreeMap<String, bool>,
+    protect_downgrades: bool,
+) -> Vec<Operation> {
+    let mut org_additions = BTreeSet::new();
+    let mut team_changes = Vec::new();
+    let mut removals = Vec::new();
+
+    let empty = BTreeMap::new();
+    for (team, members) in desired {
+        let current_members = current.teams.get(team).unwrap_or(&empty);
+        for (login, role) in members {
+            if !current.members.contains(login) {
+                org_additions.insert(login.clone());
+            }
+            match (current_members.get(login), role) {
+                (Some(current_role), role) if current_role == role => {}
+                (Some(TeamRole::Maintainer), TeamRole::Member) if protect_downgrades => {
+                    warn!(
+                        "not demoting {} to Member of {}/{}, as downgrades are protected",
+                        login, org, team
+                    );
+                }
+                (Some(TeamRole::Maintainer), TeamRole::Member) => {
+                    team_changes.push(Operation::DemoteToMember {
+                        org: org.into(),
+                        team: team.clone(),
+                        login: login.clone(),
+                    });
+                }
+                _ => team_changes.push(Operation::SetTeamMembership {
+                    org: org.into(),
+                    team: team.clone(),
+                    login: login.clone(),
+                    role: *role,
+                }),
+            }
+        }
+    }
+    for (team, members) in &current.teams {
+        let desired_members = desired.get(team).unwrap_or(&empty);
+        for login in members.keys() {
+            if !desired_members.contains_key(login) {
+                removals.push(Operation::RemoveFromTeam {
+                    org: org.into(),
+                    team: team.clone(),
+                    login: login.clone(),
+                });
+            }
+        }
+    }
+
+    let archival =
+        archived
+            .iter()
+            .filter_map(|(repo, archived)| match current.archived.get(repo) {
+                Some(current) if current != archived => Some(Operation::SetRepoArchived {
+                    owner: org.into(),
+                    repo: repo.clone(),
+                    archived: *archived,
+                }),
+                _ => None,
+            });
+
+    order_archival(
+        org_additions
+            .into_iter()
+            .map(|login| Operation::AddToOrg {
+                org: org.into(),
+                login,
+            })
+            .chain(team_changes)
+            .chain(removals)
+            .chain(archival)
+            .collect(),
+    )
+}
+
+/// Differences between the expected and actual members of a team.
+#[derive(serde::Serialize, Debug, Default, PartialEq, Eq)]
+pub struct VerificationReport {
+    pub org: String,
+    pub team: String,
+    /// Members of the team that weren't expected.
+    pub extra: Vec<String>,
+    /// Expected members missing from the team.
+    pub missing: Vec<String>,
+    pub wrong_roles: Vec<WrongRole>,
+}
+
+#[derive(serde::Serialize, Debug, PartialEq, Eq)]
+pub struct WrongRole {
+    pub login: String,
+    pub expected: TeamRole,
+    pub actual: TeamRole,
+}
+
+impl VerificationReport {
+    /// Compare the members of a team, ignoring the case of logins.
+    pub fn new(
+        org: &str,
+        team: &str,
+        expected: &[(String, TeamRole)],
+        actual: &BTreeMap<String, TeamRole>,
+    ) -> Self {
+        let actual: BTreeMap<_, _> = actual
+            .iter()
+            .map(|(login, role)| (login.to_lowercase(), (login, *role)))
+            .collect();
+        let expected: BTreeMap<_, _> = expected
+            .iter()
+            .map(|(login, role)| (login.to_lowercase(), (login, *role)))
+            .collect();
+
+        let mut report = VerificationReport {
+            org: org.into(),
+            team: team.into(),
+            ..VerificationReport::default()
+        };
+        for (key, (login, expected_role)) in &expected {
+            match actual.get(key) {
+                None => report.missing.push(login.to_string()),
+                Some((_, actual_role)) if actual_role != expected_role => {
+                    report.wrong_roles.push(WrongRole {
+                        login: login.to_string(),
+                        expected: *expected_role,
+                        actual: *actual_role,
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        for (key, (login, _)) in &actual {
+            if !expected.contains_key(key) {
+                report.extra.push(login.to_string());
+            }
+        }
+        report
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.extra.is_empty() && self.missing.is_empty() && self.wrong_roles.is_empty()
+    }
+}
+
+/// Move unarchiving repositories before all other operations and archiving
+/// them after, as changes to archived repositories fail. The order of the
+/// other operations is kept.
+pub fn order_archival(operations: Vec<Operation>) -> Vec<Operation> {
+    let (archival, mut others): (Vec<_>, Vec<_>) = operations
+        .into_iter()
+        .partition(|op| matches!(op, Operation::SetRepoArchived { .. }));
+    let (archive, unarchive): (Vec<_>, Vec<_>) = archival
+        .into_iter()
+        .partition(|op| matches!(op, Operation::SetRepoArchived { archived: true, .. }));
+    let mut ordered = unarchive;
+    ordered.append(&mut others);
+    ordered.extend(archive);
+    ordered
+}
+
+/// Organization members whose role has to change.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct OrgRolesDiff {
+    /// Members to make owners.
+    pub promote: Vec<String>,
+    /// Owners to turn back into plain members.
+    pub demote: Vec<String>,
+}
+
+/// Compute the role changes making exactly `desired` the owners, ignoring
+/// the case of logins. Refuses to demote owners unless an owner would remain
+/// right away, which would otherwise leave nobody able to administer the
+/// organization: promoting someone who isn't an active member of `members`
+/// only invites them.
+pub fn plan_org_roles(
+    org: &str,
+    current_owners: &[String],
+    members: &[String],
+    desired: &[String],
+) -> Result<OrgRolesDiff, Error> {
+    let current: BTreeSet<_> = current_owners.iter().map(|s| s.to_lowercase()).collect();
+    let active: BTreeSet<_> = members.iter().map(|s| s.to_lowercase()).collect();
+    let wanted: BTreeSet<_> = desired.iter().map(|s| s.to_lowercase()).collect();
+    let diff = OrgRolesDiff {
+        promote: desired
+            .iter()
+            .filter(|login| !current.contains(&login.to_lowercase()))
+            .cloned()
+            .collect(),
+        demote: current_owners
+            .iter()
+            .filter(|login| !wanted.contains(&login.to_lowercase()))
+            .cloned()
+            .collect(),
+    };
+    let owner_remains = wanted
+        .iter()
+        .any(|login| current.contains(login) || active.contains(login));
+    if !owner_remains && !diff.demote.is_empty() {
+        bail!(
+            "refusing to demote every owner of the {} org ({})",
+            org,
+            diff.demote.join(", ")
+        );
+    }
+    Ok(diff)
+}
+
+impl GitHubApi {
+    /// Make exactly `owners` the owners of the organization, promoting and
+    /// demoting members as needed. Nobody is removed from the organization.
+    ///
+    /// Promotions are applied first, so that demotions never leave the
+    /// organization without an owner, even transiently. In dry run mode, the
+    /// changes are only logged.
+    pub fn reconcile_org_roles(&self, org: &str, owners: &[String]) -> Result<OrgRolesDiff, Error> {
+        let logins = |users: Vec<User>| -> Vec<String> {
+            users.into_iter().map(|user| user.login).collect()
+        };
+        let current =
+            logins(self.list::<User>(&format!("orgs/{org}/members"), &[("role", "admin")])?);
+        let members = logins(self.org_members(org)?);
+        let diff = plan_org_roles(org, &current, &members, owners)?;
+        for login in &diff.promote {
+            self.set_org_membership(org, login, OrgRole::Admin)?;
+        }
+        for login in &diff.demote {
+            self.demote_org_owner(org, login)?;
+        }
+        Ok(diff)
+    }
+
+    /// Read back the members of a team and compare them with the expected
+    /// ones, to catch changes that silently didn't apply. Nothing is changed.
+    pub fn verify_team_state(
+        &self,
+        org: &str,
+        slug: &str,
+        expected: &[(String, TeamRole)],
+    ) -> Result<VerificationReport, Error> {
+        let actual = self.team_members(org, slug)?;
+        Ok(VerificationReport::new(org, slug, expected, &actual))
+    }
+
+    /// Apply one operation of a [`plan`]. In dry run mode, it's only logged.
+    pub fn apply(&self, operation: &Operation) -> Result<(), Error> {
+        match operation {
+            Operation::AddToOrg { org, login } => self.add_org_member(org, login),
+            Operation::SetTeamMembership {
+                org,
+                team,
+                login,
+                role,
+            } => self.set_team_membership(org, team, login, *role),
+            Operation::DemoteToMember { org, team, login } => {
+                self.demote_team_member(org, team, login)
+            }
+            Operation::RemoveFromTeam { org, team, login } => {
+                self.remove_team_membership(org, team, login)
+            }
+            Operation::SetRepoArchived {
+                owner,
+                repo,
+                archived,
+            } => self.set_repo_archived(owner, repo, *archived).map(drop),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::mock::{MockServer, Reply};
+    use super::*;
+
+    fn teams(teams: &[(&str, &[(&str, TeamRole)])]) -> Teams {
+        teams
+            .iter()
+            .map(|(team, members)| {
+                let members = members
+                    .iter()
+                    .map(|(login, role)| (login.to_string(), *role))
+                    .collect();
+                (team.to_string(), members)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn orders_unarchival_org_additions_team_changes_removals_then_archival() {
+        let current = OrgState {
+            members: ["alice", "bob"].iter().map(|s| s.to_string()).collect(),
+            teams: teams(&[
+                ("compiler", &[("alice", TeamRole::Member)]),
+                ("libs", &[("bob", TeamRole::Maintainer)]),
+            ]),
+            archived: [("old", false), ("revived", true), ("kept", true)]
+                .iter()
+                .map(|(repo, archived)| (repo.to_string(), *archived))
+                .collect(),
+        };
+        let desired = teams(&[
+            (
+                "compiler",
+                &[("alice", TeamRole::Maintainer), ("carol", TeamRole::Member)],
+            ),
+            ("libs", &[("alice", TeamRole::Member)]),
+        ]);
+        let archived = [
+            ("old", true),
+            ("revived", false),
+            ("kept", true),
+            ("gone", true),
+        ]
+        .iter()
+        .map(|(repo, archived)| (repo.to_string(), *archived))
+        .collect();
+
+        let plan: Vec<_> = plan("rust-lang", &current, &desired, &archived, false)
+            .iter()
+            .map(|op| op.to_string())
+            .collect();
+        assert_eq!(
+            plan,
+            [
+                "unarchive rust-lang/revived",
+                "add carol to the rust-lang org",
+                "set alice as Maintainer of rust-lang/compiler",
+                "set carol as Member of rust-lang/compiler",
+                "set alice as Member of rust-lang/libs",
+                "remove bob from rust-lang/libs",
+                "archive rust-lang/old",
+            ]
+        );
+    }
+
+    #[test]
+    fn applies_nothing_in_dry_run() {
+        let current = OrgState {
+            members: ["alice", "bob"].iter().map(|s| s.to_string()).collect(),
+            teams: teams(&[
+                ("compiler", &[("alice", TeamRole::Maintainer)]),
+                ("libs", &[("bob", TeamRole::Member)]),
+            ]),
+            archived: std::iter::once(("old".to_string(), false)).collect(),
+        };
+        let desired = teams(&[(
+            "compiler",
+            &[("alice", TeamRole::Member), ("carol", TeamRole::Maintainer)],
+        )]);
+        let archived = std::iter::once(("old".to_string(), true)).collect();
+        let operations = plan("rust-lang", &current, &desired, &archived, false);
+        assert_eq!(operations.len(), 5);
+
+        let server = MockServer::start(|_| Reply::json(200, "{}"));
+        let api = GitHubApi::from_token("secret")
+            .with_api_base(&server.addr)
+            .with_dry_run(true);
+        for operation in &operations {
+            api.apply(operation).unwrap();
+        }
+        assert!(server.finish().is_empty());
+    }
+
+    #[test]
+    fn nothing_to_do_when_in_sync() {
+        let desired = teams(&[("compiler", &[("alice", TeamRole::Member)])]);
+        let current = OrgState {
+            members: std::iter::once("alice".to_string()).collect(),
+            teams: desired.clone(),
+            ..OrgState::default()
+        };
+        assert!(plan("rust-lang", &current, &desired, &BTreeMap::new(), false).is_empty());
+    }
+
+    #[test]
+    fn downgrades_are_separate_and_can_be_protected() {
+        let current = OrgState {
+            members: ["alice", "bob"].iter().map(|s| s.to_string()).collect(),
+            teams: teams(&[(
+                "compiler",
+                &[("alice", TeamRole::Maintainer), ("bob", TeamRole::Member)],
+            )]),
+            ..OrgState::default()
+        };
+        let desired = teams(&[(
+            "compiler",
+            &[("alice", TeamRole::Member), ("bob", TeamRole::Maintainer)],
+        )]);
+
+        let describe = |protect| -> Vec<_> {
+            plan("rust-lang", &current, &desired, &BTreeMap::new(), protect)
+                .iter()
+                .map(|op| op.to_string())
+                .collect()
+        };
+        assert_eq!(
+            describe(false),
+            [
+                "demote alice to Member of rust-lang/compiler",
+                "set bob as Maintainer of rust-lang/compiler",
+            ]
+        );
+        assert_eq!(
+            describe(true),
+            ["set bob as Maintainer of rust-lang/compiler"]
+        );
+    }
+
+    #[test]
+    fn reports_team_discrepancies() {
+        let actual = teams(&[(
+            "compiler",
+            &[
+                ("Alice", TeamRole::Member),
+                ("bob", TeamRole::Member),
+                ("dave", TeamRole::Maintainer),
+            ],
+        )]);
+        let expected = vec![
+            ("alice".to_string(), TeamRole::Member),
+            ("bob".to_string(), TeamRole::Maintainer),
+            ("carol".to_string(), TeamRole::Member),
+        ];
+        let report =
+            VerificationReport::new("rust-lang", "compiler", &expected, &actual["compiler"]);
+        assert!(!report.is_ok());
+        assert_eq!(
+            serde_json::to_value(&report).unwrap(),
+            serde_json::json!({
+                "org": "rust-lang",
+                "team": "compiler",
+                "extra": ["dave"],
+                "missing": ["carol"],
+                "wrong_roles": [{ "login": "bob", "expected": "maintainer", "actual": "member" }],
+            })
+        );
+    }
+
+    #[test]
+    fn plans_org_roles() {
+        let logins =
+            |logins: &[&str]| -> Vec<String> { logins.iter().map(|s| s.to_string()).collect() };
+        let diff = plan_org_roles(
+            "rust-lang",
+            &logins(&["alice", "Bob"]),
+            &logins(&["alice", "Bob", "carol"]),
+            &logins(&["bob", "carol"]),
+        )
+        .unwrap();
+        assert_eq!(
+            diff,
+            OrgRolesDiff {
+                promote: logins(&["carol"]),
+                demote: logins(&["alice"]),
+            }
+        );
+    }
+
+    #[test]
+    fn refuses_to_demote_the_last_owner() {
+        let alice = &["alice".to_string()];
+        let err = plan_org_roles("rust-lang", alice, alice, &[]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "refusing to demote every owner of the rust-lang org (alice)"
+        );
+        assert!(plan_org_roles("rust-lang", &[], &[], &[]).is_ok());
+    }
+
+    #[test]
+    fn refuses_to_replace_owners_with_non_members() {
+        let alice = &["alice".to_string()];
+        // Promoting bob would only invite them, leaving no owner meanwhile.
+        let err = plan_org_roles("rust-lang", alice, alice, &["bob".to_string()]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "refusing to demote every owner of the rust-lang org (alice)"
+        );
+        let members = &["alice".to_string(), "Bob".to_string()];
+        let diff = plan_org_roles("rust-lang", alice, members, &["bob".to_string()]).unwrap();
+        assert_eq!(diff.promote, vec!["bob".to_string()]);
+        assert_eq!(diff.demote, vec!["alice".to_string()]);
+    }
+
+    #[test]
+    fn reconciles_no_org_roles_in_dry_run() {
+        let server = MockServer::start(|req| {
+            if req.request_line.contains("role=admin") {
+                Reply::json(200, r#"[{"login": "alice", "id": 1}]"#)
+            } else {
+                Reply::json(
+                    200,
+                    r#"[{"login": "alice", "id": 1}, {"login": "bob", "id": 2}]"#,
+                )
+            }
+        });
+        let api = GitHubApi::from_token("secret")
+            .with_api_base(&server.addr)
+            .with_dry_run(true);
+        let diff = api
+            .reconcile_org_roles("rust-lang", &["bob".to_string()])
+            .unwrap();
+        assert_eq!(diff.promote, vec!["bob".to_string()]);
+        assert_eq!(diff.demote, vec!["alice".to_string()]);
+        let received = server.finish();
+        assert!(received
+            .iter()
+            .all(|req| req.request_line.starts_with("GET ")));
+    }
+
+    #[test]
+    fn unarchives_first_and_archives_last() {
+        let archival = |repo: &str, archived| Operation::SetRepoArchived {
+            owner: "rust-lang".into(),
+            repo: repo.into(),
+            archived,
+        };
+        let operations = vec![
+            archival("old", true),
+            Operation::AddToOrg {
+                org: "rust-lang".into(),
+                login: "alice".into(),
+            },
+            archival("revived", false),
+        ];
+        let ordered: Vec<_> = order_archival(operations)
+            .iter()
+            .map(|op| op.to_string())
+            .collect();
+        assert_eq!(
+            ordered,
+            [
+                "unarchive rust-lang/revived",
+                "add alice to the rust-lang org",
+                "archive rust-lang/old",
+            ]
+        );
+    }
+}
@@ -0,0 +1,501 @@
+//! An overview of who has access to what in an organization, for audits.
+
+use super::{
+    GitHubApi, GitHubError, GraphConnection, GraphPageInfo, OrgRole, Permission, Permissions,
+    TeamRole, User, GRAPHQL_PAGE_SIZE,
+};
+use anyhow::Error;
+use std::collections::BTreeMap;
+
+/// Everyone with access to an organization and its repositories, from
+/// [`GitHubApi::access_report`].
+#[derive(serde::Serialize, Debug, Default)]
+pub struct AccessReport {
+    pub org: String,
+    /// The role of every member, keyed by login.
+    pub members: BTreeMap<String, OrgRole>,
+    /// Keyed by team slug.
+    pub teams: BTreeMap<String, TeamAccess>,
+    /// Keyed by repository name.
+    pub repos: BTreeMap<String, RepoAccess>,
+}
+
+#[derive(serde::Serialize, Debug, Default)]
+pub struct TeamAccess {
+    /// The slug of the parent team.
+    pub parent: Option<String>,
+    /// Members of the team, including those of its child teams, keyed by
+    /// login.
+    pub members: BTreeMap<String, TeamRole>,
+}
+
+#[derive(serde::Serialize, Debug, Default)]
+pub struct RepoAccess {
+    /// The permission of each team, keyed by slug.
+    pub teams: BTreeMap<String, Permission>,
+    /// The permission of users added to the repository directly, keyed by
+    /// login, or `None` if the token can't see them.
+    pub collaborators: Option<BTreeMap<String, Permission>>,
+}
+
+/// How many members, repositories or collaborators are fetched along with
+/// each team or repository. The few having more are listed separately.
+const NESTED_PAGE_SIZE: usize = 100;
+
+#[derive(serde::Deserialize)]
+struct Data<T> {
+    organization: Option<T>,
+}
+
+#[derive(serde::Serialize)]
+struct Params<'a> {
+    org: &'a str,
+    nested: usize,
+}
+
+/// [`REPOS_QUERY`] is paginated by hand, to tell its errors apart.
+#[derive(serde::Serialize)]
+struct ReposParams<'a> {
+    #[serde(flatten)]
+    params: &'a Params<'a>,
+    first: usize,
+    after: Option<&'a str>,
+}
+
+/// The first page of a connection nested in the nodes of another one.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Edges<T> {
+    edges: Vec<T>,
+    page_info: GraphPageInfo,
+}
+
+#[derive(serde::Deserialize)]
+struct Teams {
+    teams: GraphConnection<TeamNode>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TeamNode {
+    slug: String,
+    parent_team: Option<Slug>,
+    members: Edges<MemberEdge>,
+    repositories: Edges<RepoEdge>,
+}
+
+#[derive(serde::Deserialize)]
+struct Slug {
+    slug: String,
+}
+
+#[derive(serde::Deserialize)]
+struct MemberEdge {
+    role: GraphTeamRole,
+    node: Login,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+enum GraphTeamRole {
+    Member,
+    Maintainer,
+}
+
+#[derive(serde::Deserialize)]
+struct RepoEdge {
+    permission: String,
+    node: Name,
+}
+
+#[derive(serde::Deserialize)]
+struct Repositories {
+    repositories: GraphConnection<RepoNode>,
+}
+
+#[derive(serde::Deserialize)]
+struct RepoNode {
+    name: String,
+    /// Null, with a `FORBIDDEN` error, unless the token can push to the
+    /// repository.
+    collaborators: Option<Edges<CollaboratorEdge>>,
+}
+
+#[derive(serde::Deserialize)]
+struct CollaboratorEdge {
+    permission: String,
+    node: Login,
+}
+
+#[derive(serde::Deserialize)]
+struct Login {
+    login: String,
+}
+
+#[derive(serde::Deserialize)]
+struct Name {
+    name: String,
+}
+
+/// A team's repository, from the REST API.
+#[derive(serde::Deserialize)]
+struct TeamRepo {
+    name: String,
+    permissions: Permissions,
+}
+
+static TEAMS_QUERY: &str = "
+    query($org: String!, $nested: Int!, $first: Int!, $after: String) {
+        organization(login: $org) {
+            teams(first: $first, after: $after) {
+                nodes {
+                    slug
+                    parentTeam {
+                        slug
+                    }
+                    members(first: $nested) {
+                        edges {
+                            role
+                            node {
+                                login
+                            }
+                        }
+                        pageInfo {
+                            hasNextPage
+                            endCursor
+                        }
+                    }
+                    repositories(first: $nested) {
+                        edges {
+                            permission
+                            node {
+                                name
+                            }
+                        }
+                        pageInfo {
+                            hasNextPage
+                            endCursor
+                        }
+                    }
+                }
+                pageInfo {
+                    hasNextPage
+                    endCursor
+                }
+            }
+        }
+    }
+";
+
+static REPOS_QUERY: &str = "
+    query($org: String!, $nested: Int!, $first: Int!, $after: String) {
+        organization(login: $org) {
+            repositories(first: $first, after: $after) {
+                nodes {
+                    name
+                    collaborators(affiliation: DIRECT, first: $nested) {
+                        edges {
+                            permission
+                            node {
+                                login
+                            }
+                        }
+                        pageInfo {
+                            hasNextPage
+                            endCursor
+                        }
+                    }
+                }
+                pageInfo {
+                    hasNextPage
+                    endCursor
+                }
+            }
+        }
+    }
+";
+
+/// Permissions as GraphQL (`WRITE`) or the REST role names (`write`) spell
+/// them, which differ from the names used to set them (`push`).
+fn permission(name: &str) -> Permission {
+    match name {
+        "READ" | "read" => Permission::Pull,
+        "TRIAGE" => Permission::Triage,
+        "WRITE" | "write" => Permission::Push,
+        "MAINTAIN" => Permission::Maintain,
+        "ADMIN" => Permission::Admin,
+        other => Permission::from(other.to_string()),
+    }
+}
+
+impl GitHubApi {
+    /// Who has access to the organization and its repositories: the role of
+    /// every member, the members of every team, and the teams and direct
+    /// collaborators of every repository.
+    ///
+    /// Teams and repositories are fetched 100 at a time with GraphQL, along
+    /// with their members, repositories and collaborators. Only those with
+    /// more than 100 of them need more requests, so this takes a few dozen
+    /// requests even for large organizations, instead of one per team and
+    /// repository.
+    pub fn access_report(&self, org: &str) -> Result<AccessReport, Error> {
+        let mut report = AccessReport {
+            org: org.into(),
+            ..AccessReport::default()
+        };
+        for (role, name) in &[(OrgRole::Admin, "admin"), (OrgRole::Member, "member")] {
+            for user in self.list::<User>(&format!("orgs/{org}/members"), &[("role", name)])? {
+                report.members.insert(user.login, *role);
+            }
+        }
+
+        let params = Params {
+            org,
+            nested: NESTED_PAGE_SIZE,
+        };
+        let not_found = || {
+            GitHubError::NotFound {
+                what: format!("organization {org}"),
+                request_id: None,
+            }
+            .into()
+        };
+        let mut repos = Vec::new();
+        let mut after = None;
+        loop {
+            let res = self.graphql_full::<Data<Repositories>, _>(
+                REPOS_QUERY,
+                ReposParams {
+                    params: &params,
+                    first: GRAPHQL_PAGE_SIZE,
+                    after: after.as_deref(),
+                },
+            )?;
+            // Collaborators are forbidden on repositories the token can't
+            // push to, which leaves them null without failing the query.
+            if let Some(error) = res
+                .errors
+                .into_iter()
+                .find(|error| !error.is_forbidden_field("collaborators"))
+            {
+                return Err(error.into());
+            }
+            let page = res
+                .data
+                .organization
+                .map(|org| org.repositories)
+                .ok_or_else(not_found)?;
+            repos.extend(page.nodes);
+            if !page.page_info.has_next_page {
+                break;
+            }
+            after = page.page_info.end_cursor;
+        }
+        for repo in repos {
+            let collaborators = match repo.collaborators {
+                Some(collaborators) if collaborators.page_info.has_next_page => Some(
+                    self.repo_collaborators(org, &repo.name)?
+                        .into_iter()
+                        .map(|c| (c.name, permission(c.permissions.highest())))
+                        .collect(),
+                ),
+                Some(collaborators) => Some(
+                    collaborators
+                        .edges
+                        .into_iter()
+                        .map(|edge| (edge.node.login, permission(&edge.permission)))
+                        .collect(),
+                ),
+                None => None,
+            };
+            report.repos.insert(
+                repo.name,
+                RepoAccess {
+                    teams: BTreeMap::new(),
+                    collaborators,
+                },
+            );
+        }
+
+        let teams = self.graphql_paginated(TEAMS_QUERY, &params, None, |data: Data<Teams>| {
+            data.organization.map(|org| org.teams).ok_or_else(not_found)
+        })?;
+        for team in teams {
+            let members = if team.members.page_info.has_next_page {
+                self.team_members(org, &team.slug)?
+            } else {
+                team.members
+                    .edges
+                    .into_iter()
+                    .map(|edge| {
+                        let role = match edge.role {
+                            GraphTeamRole::Member => TeamRole::Member,
+                            GraphTeamRole::Maintainer => TeamRole::Maintainer,
+                        };
+                        (edge.node.login, role)
+                    })
+                    .collect()
+            };
+            let repos: Vec<_> = if team.repositories.page_info.has_next_page {
+                self.list::<TeamRepo>(&format!("orgs/{org}/teams/{}/repos", team.slug), &[])?
+                    .into_iter()
+                    .map(|repo| (repo.name, permission(repo.permissions.highest())))
+                    .collect()
+            } else {
+                team.repositories
+                    .edges
+                    .into_iter()
+                    .map(|edge| (edge.node.name, permission(&edge.permission)))
+                    .collect()
+            };
+            for (repo, permission) in repos {
+                report
+                    .repos
+                    .entry(repo)
+                    .or_default()
+                    .teams
+                    .insert(team.slug.clone(), permission);
+            }
+            report.teams.insert(
+                team.slug,
+                TeamAccess {
+                    parent: team.parent_team.map(|parent| parent.slug),
+                    members,
+                },
+            );
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::mock::{MockServer, Reply};
+    use super::*;
+
+    /// Answers the queries of [`GitHubApi::access_report`], with
+    /// `repos_errors` reported alongside the repositories.
+    fn serve_org(repos_errors: &'static str) -> MockServer {
+        MockServer::start(move |req| {
+            if req.request_line.contains("role=admin") {
+                Reply::json(200, r#"[{"login": "alice", "id": 1}]"#)
+            } else if req.request_line.contains("role=member") {
+                Reply::json(200, r#"[{"login": "bob", "id": 2}]"#)
+            } else if req.body.contains("repositories(first: $first") {
+                Reply::json(
+                    200,
+                    format!(
+                        r#"{{"data": {{"organization": {{"repositories": {{
+                            "nodes": [
+                                {{"name": "team", "collaborators": {{
+                                    "edges": [{{"permission": "WRITE", "node": {{"login": "bob"}}}}],
+                                    "pageInfo": {{"hasNextPage": false, "endCursor": null}}
+                                }}}},
+                                {{"name": "secret", "collaborators": null}}
+                            ],
+                            "pageInfo": {{"hasNextPage": false, "endCursor": null}}
+                        }}}}}}, "errors": {repos_errors}}}"#
+                    ),
+                )
+            } else {
+                Reply::json(
+                    200,
+                    r#"{"data": {"organization": {"teams": {
+                        "nodes": [{
+                            "slug": "infra",
+                            "parentTeam": null,
+                            "members": {
+                                "edges": [{"role": "MAINTAINER", "node": {"login": "alice"}}],
+                                "pageInfo": {"hasNextPage": false, "endCursor": null}
+                            },
+                            "repositories": {
+                                "edges": [{"permission": "ADMIN", "node": {"name": "team"}}],
+                                "pageInfo": {"hasNextPage": false, "endCursor": null}
+                            }
+                        }],
+                        "pageInfo": {"hasNextPage": false, "endCursor": null}
+                    }}}}"#,
+                )
+            }
+        })
+    }
+
+    #[test]
+    fn reports_forbidden_collaborators_as_unknown() {
+        let server = serve_org(
+            r#"[{
+                "type": "FORBIDDEN",
+                "path": ["organization", "repositories", "nodes", 1, "collaborators"],
+                "message": "Must have push access to view repository collaborators."
+            }]"#,
+        );
+        let api = GitHubApi::from_token("secret").with_api_base(&server.addr);
+
+        let report = api.access_report("rust-lang").unwrap();
+        assert_eq!(
+            serde_json::to_value(&report).unwrap(),
+            serde_json::json!({
+                "org": "rust-lang",
+                "members": { "alice": "admin", "bob": "member" },
+                "teams": { "infra": { "parent": null, "members": { "alice": "maintainer" } } },
+                "repos": {
+                    "team": { "teams": { "infra": "admin" }, "collaborators": { "bob": "push" } },
+                    "secret": { "teams": {}, "collaborators": null },
+                },
+            })
+        );
+        assert_eq!(server.finish().len(), 4);
+    }
+
+    #[test]
+    fn fails_on_other_graphql_errors() {
+        let server = serve_org(
+            r#"[{
+                "type": "FORBIDDEN",
+                "path": ["organization", "repositories", "nodes", 1, "name"],
+                "message": "Resource not accessible by integration"
+            }]"#,
+        );
+        let api = GitHubApi::from_token("secret").with_api_base(&server.addr);
+
+        let err = api.access_report("rust-lang").unwrap_err();
+        assert!(err.to_string().contains("Resource not accessible"));
+        server.finish();
+    }
+
+    #[test]
+    fn serializes_report() {
+        assert_eq!(permission("WRITE"), Permission::Push);
+        assert_eq!(permission("read"), Permission::Pull);
+        assert_eq!(permission("ADMIN"), Permission::Admin);
+
+        let mut report = AccessReport {
+            org: "rust-lang".into(),
+            ..AccessReport::default()
+        };
+        report.members.insert("alice".into(), OrgRole::Admin);
+        report.teams.insert(
+            "infra".into(),
+            TeamAccess {
+                parent: None,
+                members: std::iter::once(("alice".into(), TeamRole::Maintainer)).collect(),
+            },
+        );
+        report.repos.insert(
+            "team".into(),
+            RepoAccess {
+                teams: std::iter::once(("infra".into(), permission("MAINTAIN"))).collect(),
+                collaborators: None,
+            },
+        );
+        assert_eq!(
+            serde_json::to_value(&report).unwrap(),
+            serde_json::json!({
+                "org": "rust-lang",
+                "members": { "alice": "admin" },
+                "teams": { "infra": { "parent": null, "members": { "alice": "maintainer" } } },
+                "repos": { "team": { "teams": { "infra": "maintain" }, "collaborators": null } },
+            })
+        );
+    }
+}
@@ -0,0 +1,1745 @@
+//! Parts of the GitHub client that don't depend on how requests are sent:
+//! the types exchanged with the API and the logic interpreting responses.
+
+use anyhow::{bail, format_err, Error};
+use log::{debug, warn};
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use reqwest::StatusCode;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub(super) static API_BASE: &str = "https://api.github.com/";
+/// The version of the REST API requested unless configured otherwise.
+pub static DEFAULT_API_VERSION: &str = "2022-11-28";
+pub(super) static TOKEN_VAR: &str = "GITHUB_TOKEN";
+/// Set by GitHub Actions in jobs allowed to request OIDC tokens.
+pub(super) static OIDC_REQUEST_URL_VAR: &str = "ACTIONS_ID_TOKEN_REQUEST_URL";
+pub(super) static OIDC_REQUEST_TOKEN_VAR: &str = "ACTIONS_ID_TOKEN_REQUEST_TOKEN";
+/// Where [`GitHubApi::from_actions_oidc`](super::GitHubApi::from_actions_oidc)
+/// exchanges OIDC tokens, and for which audience they're requested.
+pub(super) static OIDC_EXCHANGE_URL_VAR: &str = "GITHUB_OIDC_EXCHANGE_URL";
+pub(super) static OIDC_AUDIENCE_VAR: &str = "GITHUB_OIDC_AUDIENCE";
+
+/// Media type asking GitHub for the raw content of a file or blob.
+pub(super) static RAW_MEDIA_TYPE: &str = "application/vnd.github.raw";
+/// Media type asking the contents API for the metadata of a file, which
+/// still works for files too large for it to return.
+pub(super) static OBJECT_MEDIA_TYPE: &str = "application/vnd.github.object";
+/// Media type asking GitHub for the diff of a commit or pull request.
+pub(super) static DIFF_MEDIA_TYPE: &str = "application/vnd.github.v3.diff";
+
+/// How much of an error body that isn't shaped like GitHub's is kept.
+const ERROR_SNIPPET_LEN: usize = 200;
+/// How many times a failed request is retried unless configured otherwise:
+/// not at all, callers opt into retries.
+pub(super) const DEFAULT_MAX_RETRIES: u32 = 0;
+/// Delay before the first retry, doubled for every following one.
+pub(super) const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+/// Longest we're willing to wait for a rate limit to reset before giving up.
+pub(super) const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(15 * 60);
+/// Shortest wait for a rate limit, for when GitHub says it already reset:
+/// clocks differ, and sending again right away would likely be rejected too.
+pub(super) const MIN_RATE_LIMIT_WAIT: Duration = Duration::from_secs(1);
+/// How many times a request is sent again after waiting for a rate limit,
+/// in case GitHub keeps rejecting it.
+pub(super) const MAX_RATE_LIMIT_RETRIES: u32 = 3;
+/// How much of an error response is read to describe the error.
+pub(super) const MAX_ERROR_BODY_SIZE: u64 = 64 * 1024;
+/// How many requests are in flight at the same time unless configured
+/// otherwise, which is also how many threads bulk operations use.
+pub(super) const DEFAULT_MAX_CONCURRENCY: usize = 8;
+/// How often waits check whether the client was shut down.
+pub(super) const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How long before the token expires to start warning about it.
+const TOKEN_EXPIRY_WARNING: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Source of time for the rate limit waiter and the retry backoff, so that
+/// both can be tested without actually sleeping.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+    fn sleep(&self, duration: Duration);
+}
+
+/// The real wall clock.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// Whether to send a request again after its `attempt`th attempt got a
+/// response with the given status, or failed with the given error.
+/// `max_retries` bounds the retries after failures GitHub may have seen the
+/// request for, like server errors and timeouts, and `max_connect_retries`
+/// the ones after failing to reach GitHub at all (including DNS resolution
+/// failures). Those are safe even for requests otherwise never sent twice.
+pub(super) fn should_retry(
+    outcome: Result<StatusCode, &reqwest::Error>,
+    attempt: u32,
+    max_retries: u32,
+    max_connect_retries: u32,
+) -> bool {
+    match outcome {
+        Ok(status) => status.is_server_error() && attempt < max_retries,
+        Err(err) if err.is_connect() => attempt < max_connect_retries,
+        Err(err) => err.is_timeout() && attempt < max_retries,
+    }
+}
+
+/// How many requests were sent, and how many of them were in flight at once.
+///
+/// reqwest doesn't expose its connection pool, so the peak concurrency is
+/// the closest indication of how many connections were needed: over HTTP/1.1
+/// each in-flight request holds a connection, while HTTP/2 multiplexes them.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestStats {
+    pub sent: u64,
+    pub in_flight: usize,
+    pub max_in_flight: usize,
+}
+
+pub(super) fn authorization(token: &str) -> Result<HeaderValue, Error> {
+    Ok(HeaderValue::from_str(&format!("token {}", token))?)
+}
+
+#[derive(serde::Deserialize)]
+pub struct User {
+    pub id: u64,
+    pub login: String,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    #[serde(rename = "type", default)]
+    pub kind: AccountKind,
+}
+
+impl User {
+    /// Whether the login belongs to an organization rather than a person:
+    /// the users endpoints happily return those too.
+    pub fn is_organization(&self) -> bool {
+        self.kind == AccountKind::Organization
+    }
+}
+
+/// A field of a user which can be fetched on its own with
+/// [`GitHubApi::user_fields`](super::GitHubApi::user_fields).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UserField {
+    /// The numeric id, the same as [`User::id`].
+    Id,
+    /// The GraphQL node id.
+    NodeId,
+    Name,
+    /// The public email address.
+    Email,
+    Company,
+    Location,
+}
+
+impl UserField {
+    fn graphql_name(self) -> &'static str {
+        match self {
+            UserField::Id => "databaseId",
+            UserField::NodeId => "id",
+            UserField::Name => "name",
+            UserField::Email => "email",
+            UserField::Company => "company",
+            UserField::Location => "location",
+        }
+    }
+}
+
+/// A user with only the fields that were asked for, the others being `None`.
+#[derive(serde::Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct PartialUser {
+    pub login: String,
+    #[serde(rename = "databaseId")]
+    pub id: Option<u64>,
+    #[serde(rename = "id")]
+    pub node_id: Option<String>,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub company: Option<String>,
+    pub location: Option<String>,
+}
+
+/// A query looking up `count` users at once, the login of each being passed
+/// in the `$l{i}` variable and its fields returned as `u{i}`.
+pub(super) fn user_fields_query(count: usize, fields: &[UserField]) -> String {
+    let mut selection = String::from("login");
+    for field in fields {
+        selection.push(' ');
+        selection.push_str(field.graphql_name());
+    }
+    let params: Vec<_> = (0..count).map(|i| format!("$l{}: String!", i)).collect();
+    let users: Vec<_> = (0..count)
+        .map(|i| format!("u{0}: user(login: $l{0}) {{ {1} }}", i, selection))
+        .collect();
+    format!("query({}) {{ {} }}", params.join(", "), users.join(" "))
+}
+
+#[derive(serde::Deserialize, Debug, Default, Eq, PartialEq, Copy, Clone)]
+pub enum AccountKind {
+    #[default]
+    User,
+    Organization,
+    Bot,
+    #[serde(other)]
+    Other,
+}
+
+/// Errors callers might want to handle, downcastable from [`anyhow::Error`].
+/// Those caused by a response keep its request id.
+#[derive(Debug)]
+pub enum GitHubError {
+    NotFound {
+        what: String,
+        request_id: Option<String>,
+    },
+    EnterpriseOnly {
+        feature: &'static str,
+        request_id: Option<String>,
+    },
+    GraphQlUnavailable {
+        request_id: Option<String>,
+    },
+    ResponseTooLarge {
+        url: String,
+        limit: u64,
+    },
+    UnknownCustomRole {
+        name: String,
+        request_id: Option<String>,
+    },
+    /// A user was expected, but the login belongs to an organization.
+    IsOrganization {
+        login: String,
+        request_id: Option<String>,
+    },
+    /// A repository with this name already exists.
+    RepoExists {
+        name: String,
+        request_id: Option<String>,
+    },
+    /// GitHub only returned part of a repository's tree, which was too large.
+    TreeTruncated {
+        tree: String,
+        request_id: Option<String>,
+    },
+    /// The client was shut down while waiting.
+    Cancelled,
+}
+
+impl fmt::Display for GitHubError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GitHubError::NotFound { what, request_id } => {
+                write!(f, "{} not found{}", what, RequestIdSuffix(request_id))
+            }
+            GitHubError::EnterpriseOnly {
+                feature,
+                request_id,
+            } => write!(
+                f,
+                "{} is only available on GitHub Enterprise{}",
+                feature,
+                RequestIdSuffix(request_id)
+            ),
+            GitHubError::GraphQlUnavailable { request_id } => write!(
+                f,
+                "GraphQL is unavailable on this instance{}",
+                RequestIdSuffix(request_id)
+            ),
+            GitHubError::ResponseTooLarge { url, limit } => {
+                write!(f, "the response for {} is larger than {} bytes", url, limit)
+            }
+            GitHubError::UnknownCustomRole { name, request_id } => write!(
+                f,
+                "unknown custom repository role {}{}",
+                name,
+                RequestIdSuffix(request_id)
+            ),
+            GitHubError::IsOrganization { login, request_id } => write!(
+                f,
+                "{} is an organization, not a user{}",
+                login,
+                RequestIdSuffix(request_id)
+            ),
+            GitHubError::RepoExists { name, request_id } => write!(
+                f,
+                "repository {} already exists{}",
+                name,
+                RequestIdSuffix(request_id)
+            ),
+            GitHubError::TreeTruncated { tree, request_id } => write!(
+                f,
+                "the tree of {} is too large to list at once{}",
+                tree,
+                RequestIdSuffix(request_id)
+            ),
+            GitHubError::Cancelled => write!(f, "the GitHub client was shut down"),
+        }
+    }
+}
+
+impl std::error::Error for GitHubError {}
+
+#[derive(serde::Deserialize)]
+pub(super) struct GraphResult<T> {
+    pub(super) data: Option<T>,
+    #[serde(default)]
+    pub(super) errors: Vec<GraphError>,
+    #[serde(default)]
+    pub(super) extensions: serde_json::Value,
+    #[serde(skip)]
+    pub(super) request_id: Option<String>,
+}
+
+impl<T> GraphResult<T> {
+    /// Remember which request the result came from, in it and its errors.
+    pub(super) fn set_request_id(&mut self, request_id: Option<String>) {
+        for error in &mut self.errors {
+            error.request_id = request_id.clone();
+        }
+        self.request_id = request_id;
+    }
+
+    pub(super) fn parse<R: serde::de::DeserializeOwned>(
+        &self,
+        data: serde_json::Value,
+    ) -> Result<R, Error> {
+        serde_json::from_value(data).map_err(|err| {
+            Error::from(err).context(format!(
+                "failed to parse the graphql data{}",
+                RequestIdSuffix(&self.request_id)
+            ))
+        })
+    }
+
+    /// The error to fail with when the data can't be used: the first one
+    /// GitHub reported, if any.
+    pub(super) fn into_error(self) -> Error {
+        match self.errors.into_iter().next() {
+            Some(error) => error.into(),
+            None => format_err!("missing graphql data{}", RequestIdSuffix(&self.request_id)),
+        }
+    }
+}
+
+/// The id GitHub gave to the request of a response, which its support needs
+/// to look into failures.
+pub(super) fn request_id(headers: &HeaderMap) -> Option<String> {
+    header_str(headers, "x-github-request-id").map(String::from)
+}
+
+/// Formats as ` (request id: ..)`, or nothing without a request id.
+pub(super) struct RequestIdSuffix<'a>(pub(super) &'a Option<String>);
+
+impl fmt::Display for RequestIdSuffix<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(id) => write!(f, " (request id: {})", id),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A whole GraphQL response, returned by
+/// [`GitHubApi::graphql_full`](super::GitHubApi::graphql_full) for callers
+/// interested in more than the data.
+#[derive(Debug)]
+pub struct GraphResponse<R> {
+    pub data: R,
+    /// Errors reported alongside partial data.
+    pub errors: Vec<GraphError>,
+    /// Metadata like query cost or deprecation warnings, `Null` when absent.
+    pub extensions: serde_json::Value,
+}
+
+/// An error reported in the body of a GraphQL response. GitHub returns
+/// these with a 200 status code, so they need to be classified separately.
+#[derive(serde::Deserialize, Debug)]
+pub struct GraphError {
+    message: String,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    /// The field of the data the error is about, like
+    /// `["organization", "repositories", "nodes", 0, "collaborators"]`.
+    #[serde(default)]
+    path: Option<Vec<serde_json::Value>>,
+    #[serde(skip)]
+    pub(super) request_id: Option<String>,
+}
+
+impl GraphError {
+    /// Whether the query might succeed if sent again later, like when it
+    /// timed out or hit a limit, as opposed to errors like `NOT_FOUND`.
+    pub fn is_retriable(&self) -> bool {
+        matches!(
+            self.kind.as_deref(),
+            Some("MAX_NODE_LIMIT_EXCEEDED" | "TIMEOUT" | "timedout" | "RATE_LIMITED")
+        )
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        self.kind.as_deref() == Some("NOT_FOUND")
+    }
+
+    pub fn is_rate_limited(&self) -> bool {
+        self.kind.as_deref() == Some("RATE_LIMITED")
+    }
+
+    /// Whether the token may not see the field named `field`, which GitHub
+    /// then leaves null while still returning the rest of the data.
+    pub fn is_forbidden_field(&self, field: &str) -> bool {
+        let last = self.path.as_ref().and_then(|path| path.last());
+        self.kind.as_deref() == Some("FORBIDDEN")
+            && last.and_then(|last| last.as_str()) == Some(field)
+    }
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "graphql error: {}{}",
+            self.message,
+            RequestIdSuffix(&self.request_id)
+        )
+    }
+}
+
+impl std::error::Error for GraphError {}
+
+/// An unsuccessful response from the REST API, with the details GitHub put
+/// in its body.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub url: String,
+    /// GitHub's message, or the start of the body if it doesn't come from
+    /// GitHub, like the block page of a proxy.
+    pub message: Option<String>,
+    pub errors: Vec<serde_json::Value>,
+    /// The documentation of the failed operation, often explaining which
+    /// permissions it needs.
+    pub documentation_url: Option<String>,
+    pub request_id: Option<String>,
+}
+
+impl ApiError {
+    pub(super) fn from_body(status: StatusCode, url: String, body: &str) -> Self {
+        #[derive(serde::Deserialize)]
+        struct Body {
+            message: Option<String>,
+            #[serde(default)]
+            errors: Vec<serde_json::Value>,
+            documentation_url: Option<String>,
+        }
+        let (message, errors, documentation_url) = match serde_json::from_str::<Body>(body) {
+            Ok(parsed) if parsed.message.is_some() || !parsed.errors.is_empty() => {
+                (parsed.message, parsed.errors, parsed.documentation_url)
+            }
+            _ => (snippet(body), Vec::new(), None),
+        };
+        ApiError {
+            status,
+            url,
+            message,
+            errors,
+            documentation_url,
+            request_id: None,
+        }
+    }
+}
+
+fn snippet(body: &str) -> Option<String> {
+    let body = body.split_whitespace().collect::<Vec<_>>().join(" ");
+    if body.is_empty() {
+        return None;
+    }
+    match body.char_indices().nth(ERROR_SNIPPET_LEN) {
+        Some((end, _)) => Some(format!("{}...", &body[..end])),
+        None => Some(body),
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "GitHub returned {} for {}", self.status, self.url)?;
+        if let Some(message) = &self.message {
+            write!(f, ": {}", message)?;
+        }
+        for error in &self.errors {
+            // Errors are usually objects with a message or a code, but can
+            // also be plain strings.
+            let detail = error
+                .as_str()
+                .or_else(|| error.get("message")?.as_str())
+                .or_else(|| error.get("code")?.as_str());
+            match detail {
+                Some(detail) => write!(f, "\n  - {}", detail)?,
+                None => write!(f, "\n  - {}", error)?,
+            }
+        }
+        if let Some(url) = &self.documentation_url {
+            write!(f, "\nsee {}", url)?;
+        }
+        if let Some(id) = &self.request_id {
+            write!(f, "\nrequest id: {}", id)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// The GraphQL rate limit was exhausted. Unlike the REST API, GraphQL
+/// reports its reset time as an RFC 3339 timestamp in `rateLimit.resetAt`.
+#[derive(Debug)]
+pub struct GraphQlRateLimited {
+    pub reset_at: SystemTime,
+    pub request_id: Option<String>,
+}
+
+impl GraphQlRateLimited {
+    /// How long to wait for the rate limit to reset, zero if it already did.
+    pub fn duration_until_reset(&self) -> Duration {
+        self.duration_until_reset_from(SystemTime::now())
+    }
+
+    pub fn duration_until_reset_from(&self, now: SystemTime) -> Duration {
+        self.reset_at.duration_since(now).unwrap_or_default()
+    }
+}
+
+impl fmt::Display for GraphQlRateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "GitHub GraphQL rate limit exceeded, resetting in {}s{}",
+            self.duration_until_reset().as_secs(),
+            RequestIdSuffix(&self.request_id)
+        )
+    }
+}
+
+impl std::error::Error for GraphQlRateLimited {}
+
+/// The `rateLimit.resetAt` field of a GraphQL response, if it was queried.
+pub(super) fn graphql_reset_at(data: &serde_json::Value) -> Option<SystemTime> {
+    parse_rfc3339(data.get("rateLimit")?.get("resetAt")?.as_str()?)
+}
+
+/// Strip the whitespace, commas and comments GraphQL ignores from a query,
+/// keeping a single space only where it separates two names. String
+/// literals, including block strings, are copied verbatim.
+pub(super) fn minify_graphql(query: &str) -> String {
+    fn is_name_char(c: char) -> bool {
+        c.is_ascii_alphanumeric() || c == '_'
+    }
+
+    let mut out = String::with_capacity(query.len());
+    let mut pending_space = false;
+    let mut chars = query.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() || c == ',' => pending_space = true,
+            '#' => {
+                while chars.next_if(|c| *c != '\n').is_some() {}
+                pending_space = true;
+            }
+            c => {
+                if pending_space && out.ends_with(is_name_char) && is_name_char(c) {
+                    out.push(' ');
+                }
+                pending_space = false;
+                out.push(c);
+                if c != '"' {
+                    continue;
+                }
+                let block = consume_prefix(&mut chars, "\"\"");
+                if block {
+                    out.push_str("\"\"");
+                    while let Some(c) = chars.next() {
+                        out.push(c);
+                        if c == '"' && consume_prefix(&mut chars, "\"\"") {
+                            out.push_str("\"\"");
+                            break;
+                        }
+                    }
+                } else {
+                    while let Some(c) = chars.next() {
+                        out.push(c);
+                        match c {
+                            '\\' => out.extend(chars.next()),
+                            '"' => break,
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Consume `prefix` if the iterator continues with it.
+fn consume_prefix(chars: &mut std::iter::Peekable<std::str::Chars>, prefix: &str) -> bool {
+    let mut lookahead = chars.clone();
+    for expected in prefix.chars() {
+        if lookahead.next() != Some(expected) {
+            return false;
+        }
+    }
+    *chars = lookahead;
+    true
+}
+
+/// Parse an RFC 3339 timestamp like `2024-05-01T12:00:00Z`. Fractional
+/// seconds are ignored.
+pub fn parse_rfc3339(value: &str) -> Option<SystemTime> {
+    let (date, time) = value.split_once(['T', 't', ' '])?;
+
+    let mut date = date.splitn(3, '-');
+    let year: i64 = date.next()?.parse().ok()?;
+    let month: i64 = date.next()?.parse().ok()?;
+    let day: i64 = date.next()?.parse().ok()?;
+
+    let (time, offset) = if let Some(time) = time.strip_suffix(['Z', 'z']) {
+        (time, 0)
+    } else {
+        let sign_pos = time.rfind(['+', '-'])?;
+        let (hours, minutes) = time[sign_pos + 1..].split_once(':')?;
+        let offset = hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60;
+        let sign = if time[sign_pos..].starts_with('-') {
+            -1
+        } else {
+            1
+        };
+        (&time[..sign_pos], sign * offset)
+    };
+    let time = time.split('.').next()?;
+    let mut time = time.splitn(3, ':');
+    let hour: i64 = time.next()?.parse().ok()?;
+    let minute: i64 = time.next()?.parse().ok()?;
+    let second: i64 = time.next()?.parse().ok()?;
+
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+    let secs =
+        days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second - offset;
+    Some(UNIX_EPOCH + Duration::from_secs(secs.try_into().ok()?))
+}
+
+/// Parse the `github-authentication-token-expiration` header, formatted
+/// like `2024-05-01 12:00:00 UTC` or `2024-05-01 05:00:00 -0700`.
+pub(super) fn parse_token_expiration(value: &str) -> Option<SystemTime> {
+    let (time, zone) = value.trim().rsplit_once(' ')?;
+    if zone == "UTC" {
+        return parse_rfc3339(&format!("{time}Z"));
+    }
+    if zone.len() != 5 || !zone.starts_with(['+', '-']) {
+        return None;
+    }
+    parse_rfc3339(&format!("{time}{}:{}", &zone[..3], zone.get(3..)?))
+}
+
+/// Days between the Unix epoch and a date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+#[derive(serde::Deserialize)]
+pub(super) struct GraphNodes<T> {
+    pub(super) nodes: Vec<Option<T>>,
+}
+
+/// A node of any of the kinds [`GitHubApi::resolve_mixed_nodes`] looks up,
+/// told apart by its `__typename`.
+///
+/// [`GitHubApi::resolve_mixed_nodes`]: super::GitHubApi::resolve_mixed_nodes
+#[derive(serde::Deserialize, Debug, PartialEq, Eq)]
+#[serde(tag = "__typename")]
+pub enum GraphNode {
+    User(GraphAccount),
+    Organization(GraphAccount),
+    Team(GraphTeam),
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(serde::Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphAccount {
+    pub id: String,
+    pub database_id: u64,
+    pub login: String,
+}
+
+#[derive(serde::Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphTeam {
+    pub id: String,
+    pub database_id: u64,
+    pub slug: String,
+}
+
+/// A page of a GraphQL connection.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphConnection<T> {
+    pub(super) nodes: Vec<T>,
+    pub(super) page_info: GraphPageInfo,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct GraphPageInfo {
+    pub(super) has_next_page: bool,
+    pub(super) end_cursor: Option<String>,
+}
+
+/// How many users are resolved by each query of `usernames`, at most.
+pub(super) const USERNAMES_CHUNK_SIZE: usize = 100;
+
+/// GitHub rejects GraphQL queries which could return more nodes than this.
+pub const GRAPHQL_NODE_LIMIT: u64 = 500_000;
+
+/// Estimate how many nodes a GraphQL query could return, the way GitHub does
+/// before running it. `sizes` are the sizes of nested lists, outermost first:
+/// the `first` argument of connections, or the number of `ids` looked up.
+/// Each list can return its size times as many nodes as the one containing
+/// it, and the estimate is the sum over all of them.
+pub fn graphql_node_cost(sizes: &[u64]) -> u64 {
+    sizes
+        .iter()
+        .scan(1u64, |nodes, size| {
+            *nodes = nodes.saturating_mul(*size);
+            Some(*nodes)
+        })
+        .fold(0, u64::saturating_add)
+}
+
+pub(super) fn graphql_batch_size(
+    preferred: usize,
+    nested: &[u64],
+    limit: u64,
+) -> Result<usize, Error> {
+    let per_item = graphql_node_cost(&[&[1], nested].concat());
+    let fits = limit / per_item;
+    if fits == 0 {
+        bail!(
+            "a GraphQL query for a single item could return {} nodes, more than the limit of {}",
+            per_item,
+            limit
+        );
+    }
+    Ok(fits.try_into().unwrap_or(usize::MAX).min(preferred))
+}
+
+/// Maximum number of nodes GitHub returns in a single page of a connection.
+pub(super) const GRAPHQL_PAGE_SIZE: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub remaining: u64,
+    pub reset: SystemTime,
+}
+
+impl RateLimit {
+    pub(super) fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        Some(RateLimit {
+            remaining: header_u64(headers, "x-ratelimit-remaining")?,
+            reset: UNIX_EPOCH + Duration::from_secs(header_u64(headers, "x-ratelimit-reset")?),
+        })
+    }
+}
+
+/// The separate buckets GitHub counts requests in.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RateLimitResource {
+    Core,
+    Search,
+    Graphql,
+    CodeSearch,
+    Other(String),
+}
+
+impl RateLimitResource {
+    pub(super) fn from_name(name: &str) -> Self {
+        match name {
+            "core" => RateLimitResource::Core,
+            "search" => RateLimitResource::Search,
+            "graphql" => RateLimitResource::Graphql,
+            "code_search" => RateLimitResource::CodeSearch,
+            other => RateLimitResource::Other(other.into()),
+        }
+    }
+
+    /// The bucket a response was counted in, which is the core one for
+    /// instances not saying.
+    pub(super) fn from_headers(headers: &HeaderMap) -> Self {
+        header_str(headers, "x-ratelimit-resource")
+            .map_or(RateLimitResource::Core, RateLimitResource::from_name)
+    }
+}
+
+/// Metadata of a response, for callers managing their own caching.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseMeta {
+    pub status: StatusCode,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub request_id: Option<String>,
+}
+
+impl ResponseMeta {
+    pub(super) fn from_parts(status: StatusCode, headers: &HeaderMap) -> Self {
+        let header = |name| header_str(headers, name).map(String::from);
+        ResponseMeta {
+            status,
+            etag: header(header::ETAG),
+            last_modified: header(header::LAST_MODIFIED),
+            request_id: request_id(headers),
+        }
+    }
+}
+
+/// What a client learned from the responses it got, shared between clones.
+#[derive(Default)]
+pub(super) struct ResponseState {
+    pub(super) rate_limits: HashMap<RateLimitResource, RateLimit>,
+    pub(super) last_response: Option<ResponseMeta>,
+    pub(super) enterprise_version: Option<String>,
+    pub(super) token_expiry: Option<SystemTime>,
+}
+
+impl ResponseState {
+    /// Log a response and keep what it tells about the rate limits, the
+    /// instance and the token, warning when GitHub answered with another
+    /// version of the API than `api_version` or the token expires soon.
+    pub(super) fn record(
+        &mut self,
+        status: StatusCode,
+        url: &str,
+        headers: &HeaderMap,
+        api_version: &str,
+        correlation_id: Option<&str>,
+        now: SystemTime,
+    ) {
+        debug!(
+            "{} {} (request id: {}, correlation id: {})",
+            status,
+            url,
+            request_id(headers).as_deref().unwrap_or("none"),
+            correlation_id.unwrap_or("none"),
+        );
+        if let Some(selected) = header_str(headers, "x-github-api-version-selected") {
+            if selected != api_version {
+                warn!(
+                    "requested version {} of the GitHub API, but got version {}",
+                    api_version, selected
+                );
+            }
+        }
+
+        if let Some(rate_limit) = RateLimit::from_headers(headers) {
+            let resource = RateLimitResource::from_headers(headers);
+            self.rate_limits.insert(resource, rate_limit);
+        }
+        self.last_response = Some(ResponseMeta::from_parts(status, headers));
+        let expiry = header_str(headers, "github-authentication-token-expiration")
+            .and_then(parse_token_expiration);
+        if expiry != self.token_expiry {
+            self.token_expiry = expiry;
+            let left = expiry.and_then(|expiry| expiry.duration_since(now).ok());
+            if let Some(left) = left.filter(|left| *left < TOKEN_EXPIRY_WARNING) {
+                warn!(
+                    "the GitHub token expires in {} hours",
+                    left.as_secs() / 3600
+                );
+            }
+        }
+        if self.enterprise_version.is_none() {
+            self.enterprise_version =
+                header_str(headers, "x-github-enterprise-version").map(String::from);
+        }
+    }
+}
+
+/// How long to wait before sending a request again, if its response says we
+/// ran out of rate limit.
+pub(super) fn rate_limit_wait(
+    status: StatusCode,
+    headers: &HeaderMap,
+    now: SystemTime,
+) -> Option<Duration> {
+    if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+    if let Some(retry_after) = header_u64(headers, "retry-after") {
+        return Some(Duration::from_secs(retry_after));
+    }
+    let rate_limit = RateLimit::from_headers(headers)?;
+    if rate_limit.remaining != 0 {
+        return None;
+    }
+    Some(rate_limit.reset.duration_since(now).unwrap_or_default())
+}
+
+/// Like [`rate_limit_wait`], but `None` when the rate limit resets too late
+/// to be worth waiting for, and never shorter than [`MIN_RATE_LIMIT_WAIT`].
+pub(super) fn bounded_rate_limit_wait(
+    status: StatusCode,
+    headers: &HeaderMap,
+    now: SystemTime,
+) -> Option<Duration> {
+    rate_limit_wait(status, headers, now)
+        .filter(|wait| *wait <= MAX_RATE_LIMIT_WAIT)
+        .map(|wait| wait.max(MIN_RATE_LIMIT_WAIT))
+}
+
+/// How many nodes to request in the next page of a connection, so that the
+/// last page doesn't fetch more than `limit` needs.
+pub(super) fn page_size(limit: Option<usize>, collected: usize) -> usize {
+    match limit {
+        Some(limit) => GRAPHQL_PAGE_SIZE.min(limit.saturating_sub(collected)),
+        None => GRAPHQL_PAGE_SIZE,
+    }
+}
+
+/// The number of items requested from REST list endpoints per page.
+pub(super) const REST_PAGE_SIZE: usize = 100;
+
+/// The URL of the next page, as advertised by the `Link` header.
+pub(super) fn next_link(headers: &HeaderMap) -> Option<String> {
+    parse_link_header(header_str(headers, header::LINK)?).remove("next")
+}
+
+/// The links of a `Link` header, keyed by their relation type.
+pub type LinkRels = HashMap<String, String>;
+
+/// Parse a `Link` header (RFC 8288), like the ones GitHub uses to point to
+/// the `next`, `prev`, `first` and `last` pages of a list. A link with
+/// multiple relation types is recorded under each of them, and if multiple
+/// links share a relation type the first one wins.
+pub fn parse_link_header(value: &str) -> LinkRels {
+    let mut rels = LinkRels::new();
+    for link in split_unquoted(value, ',') {
+        let mut parts = split_unquoted(link, ';').into_iter();
+        let url = match parts
+            .next()
+            .map(str::trim)
+            .and_then(|url| url.strip_prefix('<')?.strip_suffix('>'))
+        {
+            Some(url) => url,
+            None => continue,
+        };
+        for param in parts {
+            let (name, value) = match param.split_once('=') {
+                Some(param) => param,
+                None => continue,
+            };
+            if name.trim().eq_ignore_ascii_case("rel") {
+                for rel in value.trim().trim_matches('"').split_whitespace() {
+                    rels.entry(rel.to_lowercase())
+                        .or_insert_with(|| url.to_string());
+                }
+            }
+        }
+    }
+    rels
+}
+
+/// Split at `separator`, except inside quoted strings and `<>`-delimited
+/// URLs, which can both contain the separator.
+fn split_unquoted(value: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut in_quotes = false;
+    let mut in_url = false;
+    let mut start = 0;
+    for (i, c) in value.char_indices() {
+        match c {
+            '"' if !in_url => in_quotes = !in_quotes,
+            '<' if !in_quotes => in_url = true,
+            '>' if !in_quotes => in_url = false,
+            c if c == separator && !in_quotes && !in_url => {
+                parts.push(&value[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+/// Whether the error was caused by a response with the given status.
+pub(super) fn has_status(err: &Error, status: StatusCode) -> bool {
+    if let Some(err) = err.downcast_ref::<ApiError>() {
+        return err.status == status;
+    }
+    err.downcast_ref::<reqwest::Error>()
+        .and_then(|e| e.status())
+        == Some(status)
+}
+
+/// Whether GitHub rejected the request with the given error code, like
+/// `too_large` or `already_exists`.
+pub(super) fn has_error_code(err: &Error, code: &str) -> bool {
+    err.downcast_ref::<ApiError>().is_some_and(|err| {
+        err.errors
+            .iter()
+            .any(|error| error.get("code").and_then(|c| c.as_str()) == Some(code))
+    })
+}
+
+pub(super) fn is_graphql_unavailable(err: &Error) -> bool {
+    matches!(
+        err.downcast_ref(),
+        Some(GitHubError::GraphQlUnavailable { .. })
+    )
+}
+
+pub(super) fn header_str<K: header::AsHeaderName>(headers: &HeaderMap, name: K) -> Option<&str> {
+    headers.get(name)?.to_str().ok()
+}
+
+pub(super) fn header_u64<K: header::AsHeaderName>(headers: &HeaderMap, name: K) -> Option<u64> {
+    header_str(headers, name)?.parse().ok()
+}
+
+pub(super) fn user_node_id(id: u64) -> String {
+    base64::encode(format!("04:User{id}"))
+}
+
+/// Resolves the node ids of users, as built by [`user_node_id`], to their
+/// logins.
+pub(super) static USERNAMES_QUERY: &str = "
+    query($ids: [ID!]!) {
+        nodes(ids: $ids) {
+            ... on User {
+                databaseId
+                login
+            }
+        }
+    }
+";
+
+#[derive(serde::Serialize)]
+pub(super) struct UsernamesParams {
+    pub(super) ids: Vec<String>,
+}
+
+impl UsernamesParams {
+    pub(super) fn new(ids: &[u64]) -> Self {
+        UsernamesParams {
+            ids: ids.iter().map(|id| user_node_id(*id)).collect(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct Usernames {
+    pub(super) database_id: u64,
+    pub(super) login: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Organization {
+    /// The GraphQL node id.
+    pub id: String,
+    pub database_id: u64,
+    pub login: String,
+    pub name: Option<String>,
+    /// Only visible to the organization's owners.
+    pub requires_two_factor_authentication: Option<bool>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct Repo {
+    pub id: u64,
+    pub name: String,
+    pub description: Option<String>,
+    /// Archived repositories are read-only, so most changes to them fail.
+    #[serde(default)]
+    pub archived: bool,
+}
+
+/// The body archiving or unarchiving a repository.
+#[derive(serde::Serialize, Debug)]
+pub(super) struct RepoArchival {
+    pub(super) archived: bool,
+}
+
+/// The settings of a new repository, for [`GitHubApi::create_org_repo`](super::GitHubApi::create_org_repo).
+#[derive(serde::Serialize, Debug)]
+pub struct RepoSpec {
+    pub name: String,
+    pub description: Option<String>,
+    pub visibility: RepoVisibility,
+    /// A team getting read access to the repository.
+    pub team_id: Option<u64>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Eq, PartialEq, Copy, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum RepoVisibility {
+    Public,
+    Private,
+    /// Visible to the members of the enterprise, only on GitHub Enterprise.
+    Internal,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct Team {
+    pub id: u64,
+    pub slug: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub privacy: Option<TeamPrivacy>,
+    pub parent: Option<TeamParent>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct TeamParent {
+    pub id: u64,
+    pub slug: String,
+}
+
+/// The settings of a team, as passed to [`GitHubApi::ensure_team`](super::GitHubApi::ensure_team).
+#[derive(serde::Serialize, Debug)]
+pub struct TeamSpec {
+    pub name: String,
+    pub description: Option<String>,
+    pub privacy: TeamPrivacy,
+    pub parent_team_id: Option<u64>,
+}
+
+impl TeamSpec {
+    /// The slug GitHub derives from the team name.
+    pub fn slug(&self) -> String {
+        let mut slug = String::new();
+        for c in self.name.chars() {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                slug.push(c.to_ascii_lowercase());
+            } else if !slug.is_empty() && !slug.ends_with('-') {
+                slug.push('-');
+            }
+        }
+        slug.trim_end_matches('-').to_string()
+    }
+
+    pub(super) fn matches(&self, team: &Team) -> bool {
+        self.description.as_deref().unwrap_or("") == team.description.as_deref().unwrap_or("")
+            && team.privacy == Some(self.privacy)
+            && self.parent_team_id == team.parent.as_ref().map(|p| p.id)
+    }
+}
+
+/// An entry of a repository's tree, from
+/// [`GitHubApi::repo_tree`](super::GitHubApi::repo_tree).
+#[derive(serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TreeEntry {
+    /// The path relative to the listed tree.
+    pub path: String,
+    #[serde(rename = "type")]
+    pub kind: TreeEntryKind,
+    pub sha: String,
+}
+
+#[derive(serde::Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TreeEntryKind {
+    /// A file.
+    Blob,
+    /// A directory.
+    Tree,
+    /// A submodule.
+    Commit,
+    #[serde(other)]
+    Other,
+}
+
+/// A team's access to a repository.
+#[derive(serde::Deserialize, Debug)]
+pub struct RepoTeamAccess {
+    pub id: u64,
+    pub slug: String,
+    pub name: String,
+    pub permission: Permission,
+}
+
+/// Access to a repository: one of GitHub's roles, or a custom role defined
+/// by the organization, named verbatim.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(from = "String", into = "String")]
+pub enum Permission {
+    Pull,
+    Triage,
+    Push,
+    Maintain,
+    Admin,
+    Custom(String),
+}
+
+impl Permission {
+    /// The name GitHub uses for this permission.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Permission::Pull => "pull",
+            Permission::Triage => "triage",
+            Permission::Push => "push",
+            Permission::Maintain => "maintain",
+            Permission::Admin => "admin",
+            Permission::Custom(name) => name,
+        }
+    }
+
+    pub fn as_toml(&self) -> &str {
+        match self {
+            Permission::Push => "write",
+            other => other.as_str(),
+        }
+    }
+}
+
+impl From<String> for Permission {
+    fn from(name: String) -> Self {
+        match name.as_str() {
+            "pull" => Permission::Pull,
+            "triage" => Permission::Triage,
+            "push" => Permission::Push,
+            "maintain" => Permission::Maintain,
+            "admin" => Permission::Admin,
+            _ => Permission::Custom(name),
+        }
+    }
+}
+
+impl From<Permission> for String {
+    fn from(permission: Permission) -> Self {
+        match permission {
+            Permission::Custom(name) => name,
+            other => other.as_str().into(),
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Eq, PartialEq, Copy, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum TeamPrivacy {
+    Closed,
+    Secret,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Eq, PartialEq, Copy, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum TeamRole {
+    Member,
+    Maintainer,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Eq, PartialEq, Copy, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum OrgRole {
+    Member,
+    Admin,
+}
+
+#[derive(serde::Deserialize, Debug, Eq, PartialEq, Copy, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum MembershipState {
+    Active,
+    Pending,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct OrgMembership {
+    pub state: MembershipState,
+    pub role: OrgRole,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct TeamMembership {
+    pub state: MembershipState,
+    pub role: TeamRole,
+}
+
+#[derive(Debug)]
+pub struct MemberOverview {
+    /// Whether the user is an active member, rather than just invited.
+    pub member: bool,
+    /// The role in the organization, also present for pending invitations.
+    pub role: Option<OrgRole>,
+    pub teams: Vec<String>,
+}
+
+/// Outcome of a bulk operation, keyed by the login each item applied to.
+#[derive(Debug, Default)]
+pub struct BatchResult {
+    pub succeeded: Vec<String>,
+    pub failed: Vec<(String, Error)>,
+}
+
+impl fmt::Display for BatchResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} succeeded, {} failed",
+            self.succeeded.len(),
+            self.failed.len()
+        )?;
+        for (login, err) in &self.failed {
+            write!(f, "\n  {}: {}", login, err)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct Invitation {
+    pub id: u64,
+    /// Missing when the invitation was sent to an email address.
+    pub login: Option<String>,
+    pub email: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct Branch {
+    pub name: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct BranchProtection {
+    pub required_status_checks: Option<StatusChecks>,
+    pub required_pull_request_reviews: Option<RequiredReviews>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct StatusChecks {
+    pub contexts: Vec<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct RequiredReviews {
+    pub dismiss_stale_reviews: bool,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct RepoCollaborator {
+    #[serde(alias = "login")]
+    pub name: String,
+    pub permissions: Permissions,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct Permissions {
+    triage: bool,
+    push: bool,
+    maintain: bool,
+    admin: bool,
+}
+
+impl Permissions {
+    pub fn highest(&self) -> &str {
+        if self.admin {
+            "admin"
+        } else if self.maintain {
+            "maintain"
+        } else if self.push {
+            "write"
+        } else if self.triage {
+            "triage"
+        } else {
+            "read"
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static TIMEOUT_BODY: &str = r#"{
+        "data": null,
+        "errors": [{
+            "type": "timedout",
+            "message": "Something went wrong while executing your query. This may be the result of a timeout, or it could be a GitHub bug."
+        }]
+    }"#;
+
+    static NOT_FOUND_BODY: &str = r#"{
+        "data": { "nodes": [null] },
+        "errors": [{
+            "type": "NOT_FOUND",
+            "path": ["nodes", 0],
+            "locations": [{ "line": 3, "column": 17 }],
+            "message": "Could not resolve to a node with the global id of 'MDQ6VXNlcjA='"
+        }]
+    }"#;
+
+    #[test]
+    fn classifies_graphql_errors() {
+        let timeout: GraphResult<serde_json::Value> = serde_json::from_str(TIMEOUT_BODY).unwrap();
+        assert!(timeout.data.is_none());
+        assert!(timeout.errors[0].is_retriable());
+        assert!(!timeout.errors[0].is_not_found());
+
+        let mut not_found: GraphResult<serde_json::Value> =
+            serde_json::from_str(NOT_FOUND_BODY).unwrap();
+        assert!(!not_found.errors[0].is_retriable());
+        assert!(not_found.errors[0].is_not_found());
+        not_found.set_request_id(Some("C0DE:1234".into()));
+        let message = not_found.into_error().to_string();
+        assert!(message.contains("Could not resolve to a node"));
+        assert!(message.ends_with("(request id: C0DE:1234)"));
+    }
+
+    #[test]
+    fn parses_multiple_links() {
+        let rels = parse_link_header(
+            "<https://api.github.com/orgs/rust-lang/members?page=2>; rel=\"next\", \
+             <https://api.github.com/orgs/rust-lang/members?page=5>; rel=\"last\"",
+        );
+        assert_eq!(rels.len(), 2);
+        assert_eq!(
+            rels["next"],
+            "https://api.github.com/orgs/rust-lang/members?page=2"
+        );
+        assert_eq!(
+            rels["last"],
+            "https://api.github.com/orgs/rust-lang/members?page=5"
+        );
+    }
+
+    #[test]
+    fn parses_quoted_params_and_commas() {
+        let rels = parse_link_header(
+            "<https://example.com/search?q=a,b&page=1>; title=\"first, really\"; rel=first, \
+             <https://example.com/search?q=a,b&page=3>;rel=\"next last\";type=\"text/html\"",
+        );
+        assert_eq!(rels["first"], "https://example.com/search?q=a,b&page=1");
+        assert_eq!(rels["next"], "https://example.com/search?q=a,b&page=3");
+        assert_eq!(rels["last"], "https://example.com/search?q=a,b&page=3");
+        assert!(!rels.contains_key("prev"));
+    }
+
+    #[test]
+    fn ignores_malformed_links() {
+        assert!(parse_link_header("").is_empty());
+        let rels = parse_link_header("garbage; rel=next, <https://example.com/2>; rel=\"prev\"");
+        assert_eq!(rels.len(), 1);
+        assert_eq!(rels["prev"], "https://example.com/2");
+    }
+
+    #[test]
+    fn parses_rfc3339() {
+        let noon = UNIX_EPOCH + Duration::from_secs(1714564800);
+        assert_eq!(parse_rfc3339("2024-05-01T12:00:00Z"), Some(noon));
+        assert_eq!(parse_rfc3339("2024-05-01T12:00:00.250Z"), Some(noon));
+        assert_eq!(parse_rfc3339("2024-05-01T14:00:00+02:00"), Some(noon));
+        assert_eq!(parse_rfc3339("2024-05-01T07:30:00-04:30"), Some(noon));
+        assert_eq!(parse_rfc3339("1970-01-01T00:00:00Z"), Some(UNIX_EPOCH));
+        assert_eq!(parse_rfc3339("2024-13-01T12:00:00Z"), None);
+        assert_eq!(parse_rfc3339("not a date"), None);
+    }
+
+    #[test]
+    fn parses_token_expiration() {
+        let noon = UNIX_EPOCH + Duration::from_secs(1714564800);
+        assert_eq!(
+            parse_token_expiration("2024-05-01 12:00:00 UTC"),
+            Some(noon)
+        );
+        assert_eq!(
+            parse_token_expiration("2024-05-01 05:00:00 -0700"),
+            Some(noon)
+        );
+        assert_eq!(parse_token_expiration("2024-05-01 12:00:00"), None);
+    }
+
+    #[test]
+    fn graphql_rate_limit_reset() {
+        let data = serde_json::json!({
+            "rateLimit": { "remaining": 0, "resetAt": "2024-05-01T12:00:00Z" },
+        });
+        let limited = GraphQlRateLimited {
+            reset_at: graphql_reset_at(&data).unwrap(),
+            request_id: None,
+        };
+        let before = UNIX_EPOCH + Duration::from_secs(1714564800 - 90);
+        assert_eq!(
+            limited.duration_until_reset_from(before),
+            Duration::from_secs(90)
+        );
+        // A reset in the past means there is nothing to wait for.
+        let after = UNIX_EPOCH + Duration::from_secs(1714564800 + 90);
+        assert_eq!(limited.duration_until_reset_from(after), Duration::ZERO);
+    }
+
+    #[test]
+    fn api_error_includes_documentation_url() {
+        let err = ApiError::from_body(
+            StatusCode::FORBIDDEN,
+            "https://api.github.com/orgs/rust-lang/teams".into(),
+            r#"{
+                "message": "Resource not accessible by integration",
+                "documentation_url": "https://docs.github.com/rest/teams/teams#list-teams"
+            }"#,
+        );
+        assert_eq!(
+            err.to_string(),
+            "GitHub returned 403 Forbidden for https://api.github.com/orgs/rust-lang/teams: \
+             Resource not accessible by integration\n\
+             see https://docs.github.com/rest/teams/teams#list-teams"
+        );
+    }
+
+    #[test]
+    fn api_error_includes_validation_errors() {
+        let err = ApiError::from_body(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "https://api.github.com/orgs/rust-lang/teams".into(),
+            r#"{
+                "message": "Validation Failed",
+                "errors": [
+                    { "resource": "Team", "code": "already_exists", "field": "name" },
+                    { "message": "Name must be unique" },
+                    "plain error"
+                ]
+            }"#,
+        );
+        assert_eq!(
+            err.to_string(),
+            "GitHub returned 422 Unprocessable Entity for https://api.github.com/orgs/rust-lang/teams: \
+             Validation Failed\n  - already_exists\n  - Name must be unique\n  - plain error"
+        );
+    }
+
+    #[test]
+    fn api_error_falls_back_to_body_snippet() {
+        let page = format!(
+            "<html>\n  <head><title>Access denied</title></head>\n  <body>{}</body>\n</html>",
+            "blocked ".repeat(50)
+        );
+        let err = ApiError::from_body(
+            StatusCode::FORBIDDEN,
+            "https://ghe.example.com/api/v3/user".into(),
+            &page,
+        );
+        let message = err.message.unwrap();
+        assert!(
+            message.starts_with("<html> <head><title>Access denied</title></head> <body>blocked")
+        );
+        assert!(message.ends_with("..."));
+        assert_eq!(message.chars().count(), ERROR_SNIPPET_LEN + 3);
+
+        let err = ApiError::from_body(
+            StatusCode::BAD_GATEWAY,
+            "url".into(),
+            r#"{"error":"upstream"}"#,
+        );
+        assert_eq!(err.message.as_deref(), Some(r#"{"error":"upstream"}"#));
+        let err = ApiError::from_body(StatusCode::BAD_GATEWAY, "url".into(), "");
+        assert_eq!(err.message, None);
+    }
+
+    #[test]
+    fn retries_server_errors_within_budget() {
+        assert!(should_retry(Ok(StatusCode::BAD_GATEWAY), 0, 1, 1));
+        assert!(!should_retry(Ok(StatusCode::BAD_GATEWAY), 1, 1, 1));
+        // Only failing to reach GitHub gets the connection budget.
+        assert!(!should_retry(Ok(StatusCode::BAD_GATEWAY), 0, 0, 3));
+        assert!(!should_retry(Ok(StatusCode::NOT_FOUND), 0, 3, 3));
+    }
+
+    #[test]
+    fn page_size_respects_limit() {
+        assert_eq!(page_size(None, 0), 100);
+        assert_eq!(page_size(None, 500), 100);
+        assert_eq!(page_size(Some(20), 0), 20);
+        assert_eq!(page_size(Some(250), 200), 50);
+        assert_eq!(page_size(Some(20), 20), 0);
+    }
+
+    #[test]
+    fn team_slug_from_name() {
+        let spec = |name: &str| TeamSpec {
+            name: name.into(),
+            description: None,
+            privacy: TeamPrivacy::Closed,
+            parent_team_id: None,
+        };
+        assert_eq!(spec("compiler").slug(), "compiler");
+        assert_eq!(spec("Release Team").slug(), "release-team");
+        assert_eq!(spec("wg-async_foundations").slug(), "wg-async_foundations");
+        assert_eq!(spec("Infra (ops)!").slug(), "infra-ops");
+    }
+
+    #[test]
+    fn permission_names_roundtrip() {
+        for name in &["pull", "push", "admin", "security-reviewer"] {
+            let permission: Permission = serde_json::from_value((*name).into()).unwrap();
+            assert_eq!(serde_json::to_value(&permission).unwrap(), *name);
+        }
+        let custom: Permission = serde_json::from_str(r#""security-reviewer""#).unwrap();
+        assert_eq!(custom, Permission::Custom("security-reviewer".into()));
+        assert_eq!(Permission::Push.as_toml(), "write");
+    }
+
+    #[test]
+    fn minifies_graphql_outside_strings() {
+        let query = r#"
+            query($ids: [ID!]!, $name: String) {
+                # Comments go away.
+                nodes(ids: $ids) {
+                    ... on User {
+                        databaseId
+                        login
+                    }
+                }
+                search(query: "  spaced ,  \"quoted\"  # not a comment", type: USER) {
+                    userCount
+                }
+                description(text: """  block,
+                   string  """)
+            }
+        "#;
+        assert_eq!(
+            minify_graphql(query),
+            r#"query($ids:[ID!]!$name:String){nodes(ids:$ids){...on User{databaseId login}}search(query:"  spaced ,  \"quoted\"  # not a comment"type:USER){userCount}description(text:"""  block,
+                   string  """)}"#
+        );
+    }
+
+    #[test]
+    fn rate_limit_resource_from_headers() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            RateLimitResource::from_headers(&headers),
+            RateLimitResource::Core
+        );
+        headers.insert("x-ratelimit-resource", "code_search".parse().unwrap());
+        assert_eq!(
+            RateLimitResource::from_headers(&headers),
+            RateLimitResource::CodeSearch
+        );
+        headers.insert("x-ratelimit-resource", "audit_log".parse().unwrap());
+        assert_eq!(
+            RateLimitResource::from_headers(&headers),
+            RateLimitResource::Other("audit_log".into())
+        );
+    }
+
+    #[test]
+    fn deserializes_mixed_nodes() {
+        let nodes: GraphNodes<GraphNode> = serde_json::from_str(
+            r#"{
+                "nodes": [
+                    { "__typename": "User", "id": "U_1", "databaseId": 1, "login": "alice" },
+                    { "__typename": "Team", "id": "T_2", "databaseId": 2, "slug": "compiler" },
+                    { "__typename": "Organization", "id": "O_3", "databaseId": 3, "login": "rust-lang" },
+                    { "__typename": "Repository" },
+                    null
+                ]
+            }"#,
+        )
+        .unwrap();
+        let account = |id: &str, database_id, login: &str| GraphAccount {
+            id: id.into(),
+            database_id,
+            login: login.into(),
+        };
+        assert_eq!(
+            nodes.nodes,
+            [
+                Some(GraphNode::User(account("U_1", 1, "alice"))),
+                Some(GraphNode::Team(GraphTeam {
+                    id: "T_2".into(),
+                    database_id: 2,
+                    slug: "compiler".into(),
+                })),
+                Some(GraphNode::Organization(account("O_3", 3, "rust-lang"))),
+                Some(GraphNode::Unknown),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn serializes_repo_archival() {
+        assert_eq!(
+            serde_json::to_string(&RepoArchival { archived: true }).unwrap(),
+            r#"{"archived":true}"#
+        );
+    }
+
+    #[test]
+    fn parses_tree_entries() {
+        let entries: Vec<TreeEntry> = serde_json::from_str(
+            r#"[
+                { "path": ".github", "mode": "040000", "type": "tree", "sha": "a1" },
+                { "path": ".github/CODEOWNERS", "mode": "100644", "type": "blob", "sha": "b2", "size": 42 },
+                { "path": "vendor/lib", "mode": "160000", "type": "commit", "sha": "c3" }
+            ]"#,
+        )
+        .unwrap();
+        let kinds: Vec<_> = entries.iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                TreeEntryKind::Tree,
+                TreeEntryKind::Blob,
+                TreeEntryKind::Commit
+            ]
+        );
+        assert_eq!(entries[1].path, ".github/CODEOWNERS");
+    }
+
+    #[test]
+    fn estimates_graphql_node_cost() {
+        // 100 repositories, each with 50 issues with 10 labels each.
+        assert_eq!(graphql_node_cost(&[100, 50, 10]), 100 + 5_000 + 50_000);
+        assert_eq!(
+            graphql_batch_size(100, &[], GRAPHQL_NODE_LIMIT).unwrap(),
+            100
+        );
+        assert_eq!(
+            graphql_batch_size(100, &[100, 100], GRAPHQL_NODE_LIMIT).unwrap(),
+            49
+        );
+        assert!(graphql_batch_size(100, &[1_000, 1_000], GRAPHQL_NODE_LIMIT).is_err());
+    }
+
+    #[test]
+    fn builds_user_fields_query() {
+        assert_eq!(
+            user_fields_query(2, &[UserField::Id, UserField::Email]),
+            "query($l0: String!, $l1: String!) { \
+             u0: user(login: $l0) { login databaseId email } \
+             u1: user(login: $l1) { login databaseId email } }"
+        );
+    }
+}
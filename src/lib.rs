@@ -0,0 +1,6 @@
+//! The GitHub clients the `rust-team` CLI is built on, for other tools to
+//! depend on.
+
+pub mod github;
+
+pub const USER_AGENT: &str = "https://github.com/rust-lang/team (infra@rust-lang.org)";
@@ -3,15 +3,13 @@
 mod data;
 #[macro_use]
 mod permissions;
-mod github;
 mod schema;
 mod static_api;
 mod validate;
 mod zulip;
 
-const USER_AGENT: &str = "https://github.com/rust-lang/team (infra@rust-lang.org)";
-
 use data::Data;
+use rust_team::{github, USER_AGENT};
 use schema::{Email, Team, TeamKind};
 
 use crate::schema::RepoPermission;
@@ -95,7 +95,7 @@ impl Person {
         }
     }
 
-    pub(crate) fn email(&self) -> Email {
+    pub(crate) fn email(&self) -> Email<'_> {
         match &self.email {
             EmailField::Disabled(false) => Email::Disabled,
             EmailField::Disabled(true) => Email::Missing,
@@ -120,9 +120,10 @@ impl Person {
     }
 }
 
-#[derive(serde_derive::Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(serde_derive::Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
 #[serde(rename_all = "kebab-case")]
 pub(crate) enum TeamKind {
+    #[default]
     Team,
     WorkingGroup,
     ProjectGroup,
@@ -144,12 +145,6 @@ impl std::fmt::Display for TeamKind {
     }
 }
 
-impl Default for TeamKind {
-    fn default() -> Self {
-        Self::Team
-    }
-}
-
 #[derive(serde_derive::Deserialize, Debug)]
 #[serde(deny_unknown_fields, rename_all = "kebab-case")]
 pub(crate) struct Team {
@@ -490,6 +485,7 @@ impl DiscordRole {
     }
 }
 
+#[allow(unused)]
 #[derive(Eq, PartialEq, Debug)]
 pub(crate) struct DiscordTeam {
     pub(crate) members: Vec<u64>,
@@ -625,7 +621,7 @@ impl WebsiteData {
         self.repo.as_deref()
     }
 
-    pub(crate) fn discord(&self) -> Option<DiscordInvite> {
+    pub(crate) fn discord(&self) -> Option<DiscordInvite<'_>> {
         if let (Some(url), Some(channel)) = (&self.discord_invite, &self.discord_name) {
             Some(DiscordInvite {
                 url: url.as_ref(),
@@ -56,7 +56,7 @@ fn static_api() -> Result<(), Error> {
                 ansi_term::Color::Red.bold().paint("!!! the file"),
                 ansi_term::Color::White
                     .bold()
-                    .paint(&file.to_str().unwrap().to_string()),
+                    .paint(file.to_str().unwrap().to_string()),
                 ansi_term::Color::Red.bold().paint("does not match"),
             );
             println!("{}", changeset);
@@ -92,7 +92,7 @@ fn step(name: &str) {
         "{}",
         ansi_term::Color::White
             .bold()
-            .paint(&format!("==> {}", name))
+            .paint(format!("==> {}", name))
     );
 }
 